@@ -1,11 +1,23 @@
+use crate::clocking::clock_holds;
+use crate::migration::migrate_simulation_file;
+use crate::signal::{
+    cell_signals, clock_name, SampleWindow, SignalDescriptor, SignalIndex, SignalKind,
+    SignalSource, CLOCK_COUNT,
+};
+use crate::signal_expr::{evaluate_derived, DerivedSignal};
+use crate::sim_cache::{LoadedSimulation, SimulationAccess, SimulationCache};
+use crate::sim_frame::{SampleType, SimulationFrame};
+use crate::sim_header::read_simulation_header;
+use crate::truth_table::{cell_columns, parse_cell_clock_delay, TruthTableSettings};
 use qca_core::analysis::truth_table::{generate_truth_table, TruthTable};
 use qca_core::design::file::QCADesign;
-use qca_core::objects::cell::QCACellIndex;
-use qca_core::simulation::file::{read_from_file, QCASimulationMetadata};
+use qca_core::simulation::file::QCASimulationMetadata;
+use serde::Serialize;
 use std::collections::HashMap;
-use std::fs::File;
 use std::str::FromStr;
 use tauri::http::Request;
+use tauri::ipc::Response;
+use tauri::{AppHandle, Manager, State};
 use urlencoding::decode;
 
 fn parse_query_params(query: &str) -> HashMap<String, String> {
@@ -18,26 +30,20 @@ fn parse_query_params(query: &str) -> HashMap<String, String> {
         .collect::<HashMap<String, String>>()
 }
 
-fn f64_vec_to_u8_vec(floats: Vec<f64>) -> Vec<u8> {
-    // Calculate the byte size
-    let num_floats = floats.len();
-    let num_bytes = num_floats * std::mem::size_of::<f64>();
-
-    // Create a vector with the exact capacity needed
-    let mut bytes = Vec::with_capacity(num_bytes);
-
-    // Convert each f64 to its byte representation
-    for &value in &floats {
-        // Convert f64 to bytes in native endianness
-        let value_bytes = value.to_ne_bytes();
-        // Extend the bytes vector with these 8 bytes
-        bytes.extend_from_slice(&value_bytes);
+fn parse_query_param<T: FromStr>(
+    query_params: &HashMap<String, String>,
+    name: &str,
+) -> Result<Option<T>, String> {
+    match query_params.get(name) {
+        Some(value) => value
+            .parse::<T>()
+            .map(Some)
+            .map_err(|_err| format!("Invalid query parameter '{}'", name)),
+        None => Ok(None),
     }
-
-    bytes
 }
 
-pub fn handle_load_sim(request: Request<Vec<u8>>) -> Result<Vec<u8>, String> {
+pub fn handle_load_sim(app: &AppHandle, request: Request<Vec<u8>>) -> Result<Vec<u8>, String> {
     let query = request.uri().query().ok_or("Missing query parameters")?;
     let query_decoded = decode(query).map_err(|err| "Decoding failed")?;
     let query_params = parse_query_params(query_decoded.as_ref());
@@ -54,81 +60,188 @@ pub fn handle_load_sim(request: Request<Vec<u8>>) -> Result<Vec<u8>, String> {
             .map_err(|err| "Invalid indices formatting")?;
     }
 
-    let file = File::open(filename).map_err(|err| "File cannot be opened")?;
-
-    let (design, data) = read_from_file(file)?;
-
-    let num_samples = data.metadata.num_samples;
-    let num_floats = 4 + data
-        .metadata
-        .stored_cells
-        .iter()
-        .map(|index| {
-            let layer = design.layers.get(index.layer).unwrap();
-            let architecture = design
-                .cell_architectures
-                .get(layer.cell_architecture_id.as_str())
-                .unwrap();
-            architecture.dot_count as usize / 4
-        })
-        .sum::<usize>();
+    let sample_type = match query_params.get("dtype") {
+        Some(dtype) => SampleType::from_str(dtype)?,
+        None => SampleType::F64,
+    };
+
+    let derived = match query_params.get("derived") {
+        Some(derived) => serde_json::from_str::<Vec<DerivedSignal>>(derived)
+            .map_err(|_err| "Invalid derived signals formatting")?,
+        None => vec![],
+    };
+
+    let simulation = app.state::<SimulationCache>().access(filename)?;
+    load_simulation_frame(
+        simulation.source(),
+        data_indices,
+        &derived,
+        parse_query_param(&query_params, "start")?,
+        parse_query_param(&query_params, "end")?,
+        parse_query_param(&query_params, "stride")?,
+        sample_type,
+    )
+}
 
-    let mut result: Vec<f64> = Vec::with_capacity(num_samples * num_floats);
+#[tauri::command(async)]
+#[allow(clippy::too_many_arguments)]
+pub fn load_simulation_window(
+    cache: State<'_, SimulationCache>,
+    filename: String,
+    indices: Option<Vec<usize>>,
+    start: Option<usize>,
+    end: Option<usize>,
+    stride: Option<usize>,
+    dtype: Option<String>,
+    derived: Option<Vec<DerivedSignal>>,
+) -> Result<Response, String> {
+    let sample_type = match dtype {
+        Some(dtype) => SampleType::from_str(&dtype)?,
+        None => SampleType::F64,
+    };
+
+    let simulation = cache.access(&filename)?;
+    let frame = load_simulation_frame(
+        simulation.source(),
+        indices.unwrap_or_default(),
+        &derived.unwrap_or_default(),
+        start,
+        end,
+        stride,
+        sample_type,
+    )?;
+    Ok(Response::new(frame))
+}
 
-    for clock in &data.clock_data {
-        result.extend_from_slice(&clock);
+fn load_simulation_frame(
+    simulation: &dyn SignalSource,
+    mut data_indices: Vec<usize>,
+    derived: &[DerivedSignal],
+    start: Option<usize>,
+    end: Option<usize>,
+    stride: Option<usize>,
+    sample_type: SampleType,
+) -> Result<Vec<u8>, String> {
+    let (design, metadata) = (simulation.design(), simulation.metadata());
+
+    let num_samples = metadata.num_samples;
+    let window = SampleWindow::new(start, end, stride, num_samples)?;
+    let mut frame = SimulationFrame::new(window, num_samples, sample_type);
+
+    for clock in 0..CLOCK_COUNT {
+        let descriptor = SignalDescriptor {
+            index: SignalIndex {
+                kind: SignalKind::Clock,
+                index: clock,
+                subindex: None,
+            },
+            name: clock_name(clock),
+            cell: None,
+        };
+        let view = simulation.signal_view(&descriptor.index)?;
+        frame.push_view(descriptor, &view);
     }
 
     if data_indices.len() == 0 {
-        data_indices = (0..data.metadata.stored_cells.len()).collect();
+        data_indices = (0..metadata.stored_cells.len()).collect();
     }
     for i in data_indices {
-        let data_ref = data.cells_data.get(i).unwrap().data.as_ref();
-        result.extend_from_slice(data_ref);
+        let cell = metadata
+            .stored_cells
+            .get(i)
+            .ok_or(format!("Stored cell {} does not exist", i))?;
+        for descriptor in cell_signals(design, i, cell)? {
+            let view = simulation.signal_view(&descriptor.index)?;
+            frame.push_view(descriptor, &view);
+        }
     }
 
-    Ok(f64_vec_to_u8_vec(result))
+    for (i, (signal, samples)) in derived
+        .iter()
+        .zip(evaluate_derived(simulation, derived, &window)?)
+        .enumerate()
+    {
+        let descriptor = SignalDescriptor {
+            index: SignalIndex {
+                kind: SignalKind::Derived,
+                index: i,
+                subindex: None,
+            },
+            name: signal.name.clone(),
+            cell: None,
+        };
+        frame.push_samples(descriptor, samples);
+    }
+
+    Ok(frame.encode())
 }
 
-#[tauri::command]
+/// Returns the design and metadata of a simulation from its header, after
+/// migrating files from older versions. The sample data is loaded into the
+/// cache on the first data request.
+#[tauri::command(async)]
 pub fn load_simulation_file(
     filename: String,
 ) -> Result<(QCADesign, QCASimulationMetadata), String> {
-    let file = File::open(filename).map_err(|err| "File cannot be opened")?;
-    let (design, data) = read_from_file(file)?;
+    migrate_simulation_file(&filename)?;
+    let header = read_simulation_header(&filename)?;
 
-    Ok((design, data.metadata))
+    Ok((header.design, header.metadata))
 }
 
-#[tauri::command]
+/// A truth table as shown by the analyzer: the requested cells in their
+/// order, with binary values or the ternary values −1, 0 and +1.
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum CalculatedTruthTable {
+    Binary(TruthTable),
+    Ternary {
+        entries: Vec<(String, Vec<Option<i32>>)>,
+    },
+}
+
+/// Binary tables are generated by qca-core; ternary tables, which it does
+/// not support, are read from the hold phases of the clock zones.
+#[tauri::command(async)]
 pub fn calculate_truth_table(
+    cache: State<'_, SimulationCache>,
     filename: String,
-    cells: Vec<QCACellIndex>,
-    cell_clock_delay: HashMap<String, usize>,
-    clock_threshold: f64,
-    logical_threshold: f64,
-    value_threshold: f64,
-) -> Result<TruthTable, String> {
-    let file = File::open(filename).map_err(|err| "File cannot be opened")?;
-    let (design, simulation) = read_from_file(file)?;
-
-    let cell_clock_delay = cell_clock_delay
-        .into_iter()
-        .map(|(k, v)| {
-            let cell_index = QCACellIndex::from_str(&k).unwrap();
-            (cell_index, v)
-        })
-        .collect::<HashMap<QCACellIndex, usize>>();
-
-    let truth_table = generate_truth_table(
-        &design,
-        &simulation,
-        &cells,
-        cell_clock_delay,
-        clock_threshold,
-        logical_threshold,
-        value_threshold,
-    );
-
-    Ok(truth_table)
+    settings: TruthTableSettings,
+) -> Result<CalculatedTruthTable, String> {
+    let access = cache.access(&filename)?;
+    if settings.ternary.is_none() {
+        let cell_clock_delay = parse_cell_clock_delay(&settings.cell_clock_delay)?;
+        let generate = |simulation: &LoadedSimulation| {
+            generate_truth_table(
+                &simulation.design,
+                &simulation.data,
+                &settings.cells,
+                cell_clock_delay.clone(),
+                settings.clock_threshold,
+                settings.logical_threshold,
+                settings.value_threshold,
+            )
+        };
+        let truth_table = match &access {
+            SimulationAccess::Loaded(simulation) => generate(simulation),
+            SimulationAccess::Mapped(simulation) => generate(&simulation.load()?),
+        };
+        return Ok(CalculatedTruthTable::Binary(truth_table));
+    }
+
+    let simulation = access.source();
+    let holds = clock_holds(simulation, settings.clock_threshold)?;
+    Ok(CalculatedTruthTable::Ternary {
+        entries: cell_columns(simulation, &settings, &holds)?
+            .into_iter()
+            .map(|column| {
+                let values = column
+                    .values
+                    .iter()
+                    .map(|value| value.map(|value| value as i32))
+                    .collect();
+                (column.name, values)
+            })
+            .collect(),
+    })
 }
@@ -0,0 +1,433 @@
+//! Headless rendering of a simulation as an animation of the layout, with
+//! cells coloured by polarization and outlined in their clock zone colour.
+
+use crate::clocking::cell_clock_zone;
+use crate::signal::{
+    cell_signals, dot_signs, SampleWindow, SignalIndex, SignalKind, SignalView, CLOCK_COUNT,
+};
+use crate::sim_cache::SimulationCache;
+use qca_core::objects::cell::QCACellIndex;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use tauri::State;
+
+const BACKGROUND: [u8; 3] = [255, 255, 255];
+const UNSTORED_CELL: [u8; 3] = [225, 225, 225];
+const POSITIVE: [u8; 3] = [220, 50, 47];
+const NEGATIVE: [u8; 3] = [38, 139, 210];
+const DOT: [u8; 3] = [40, 40, 40];
+const ZONE_COLORS: [[u8; 3]; 4] = [
+    [46, 160, 67],
+    [155, 89, 182],
+    [26, 188, 156],
+    [120, 120, 120],
+];
+const MARGIN: f64 = 16.0;
+const DEFAULT_FRAME_COUNT: usize = 100;
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnimationFormat {
+    Gif,
+    Apng,
+    /// Numbered PNG files in the output directory, e.g. for encoding to MP4.
+    PngSequence,
+}
+
+#[derive(Deserialize)]
+pub struct AnimationOptions {
+    format: AnimationFormat,
+    width: u32,
+    height: u32,
+    frame_rate: f64,
+    start: Option<usize>,
+    end: Option<usize>,
+    frame_count: Option<usize>,
+    /// Renders only this layer, all layers by default.
+    layer: Option<usize>,
+}
+
+struct Canvas {
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+}
+
+impl Canvas {
+    fn new(width: u32, height: u32) -> Canvas {
+        let (width, height) = (width as usize, height as usize);
+        Canvas {
+            width,
+            height,
+            // Opaque, the colour is set by `clear`
+            pixels: vec![255; width * height * 4],
+        }
+    }
+
+    fn clear(&mut self) {
+        for pixel in self.pixels.chunks_mut(4) {
+            pixel[..3].copy_from_slice(&BACKGROUND);
+        }
+    }
+
+    fn set(&mut self, x: i64, y: i64, color: [u8; 3]) {
+        if x < 0 || y < 0 || x >= self.width as i64 || y >= self.height as i64 {
+            return;
+        }
+        let offset = (y as usize * self.width + x as usize) * 4;
+        self.pixels[offset..offset + 3].copy_from_slice(&color);
+    }
+
+    fn fill_rect(&mut self, x0: f64, y0: f64, x1: f64, y1: f64, color: [u8; 3]) {
+        for y in y0.round() as i64..y1.round() as i64 {
+            for x in x0.round() as i64..x1.round() as i64 {
+                self.set(x, y, color);
+            }
+        }
+    }
+
+    fn fill_circle(&mut self, cx: f64, cy: f64, radius: f64, color: [u8; 3]) {
+        let r = radius.ceil() as i64;
+        for dy in -r..=r {
+            for dx in -r..=r {
+                if ((dx * dx + dy * dy) as f64) <= radius * radius {
+                    self.set(cx.round() as i64 + dx, cy.round() as i64 + dy, color);
+                }
+            }
+        }
+    }
+}
+
+fn blend(from: [u8; 3], to: [u8; 3], amount: f64) -> [u8; 3] {
+    let amount = amount.clamp(0.0, 1.0);
+    let mut color = [0; 3];
+    for i in 0..3 {
+        color[i] = (from[i] as f64 + (to[i] as f64 - from[i] as f64) * amount).round() as u8;
+    }
+    color
+}
+
+fn polarization_color(polarization: f64) -> [u8; 3] {
+    match polarization >= 0.0 {
+        true => blend([255, 255, 255], POSITIVE, polarization),
+        false => blend([255, 255, 255], NEGATIVE, -polarization),
+    }
+}
+
+/// A cell in canvas coordinates.
+struct CellSprite<'a> {
+    center: [f64; 2],
+    size: f64,
+    zone: usize,
+    /// Dot positions with the polarization and sign each contributes to.
+    dots: Vec<([f64; 2], usize, f64)>,
+    dot_radius: f64,
+    polarizations: Option<Vec<SignalView<'a>>>,
+}
+
+/// Maps design coordinates onto the canvas, flipping y so it points up.
+struct Viewport {
+    min: [f64; 2],
+    max_y: f64,
+    scale: f64,
+    offset: [f64; 2],
+}
+
+impl Viewport {
+    fn fit(cells: &[(QCACellIndex, [f64; 2], f64)], width: u32, height: u32) -> Viewport {
+        let (mut min, mut max) = ([f64::INFINITY; 2], [f64::NEG_INFINITY; 2]);
+        for (_, position, size) in cells {
+            for axis in 0..2 {
+                min[axis] = min[axis].min(position[axis] - size / 2.0);
+                max[axis] = max[axis].max(position[axis] + size / 2.0);
+            }
+        }
+        let extent = [(max[0] - min[0]).max(1.0), (max[1] - min[1]).max(1.0)];
+        let available = [
+            (width as f64 - 2.0 * MARGIN).max(1.0),
+            (height as f64 - 2.0 * MARGIN).max(1.0),
+        ];
+        let scale = (available[0] / extent[0]).min(available[1] / extent[1]);
+        Viewport {
+            min,
+            max_y: max[1],
+            scale,
+            offset: [
+                (width as f64 - extent[0] * scale) / 2.0,
+                (height as f64 - extent[1] * scale) / 2.0,
+            ],
+        }
+    }
+
+    fn map(&self, point: [f64; 2]) -> [f64; 2] {
+        [
+            self.offset[0] + (point[0] - self.min[0]) * self.scale,
+            self.offset[1] + (self.max_y - point[1]) * self.scale,
+        ]
+    }
+}
+
+fn draw_frame(canvas: &mut Canvas, sprites: &[CellSprite], clock_levels: &[f64], sample: usize) {
+    canvas.clear();
+    for sprite in sprites {
+        let half = sprite.size / 2.0;
+        let [x, y] = sprite.center;
+        let clock_level = clock_levels.get(sprite.zone).copied().unwrap_or(0.0);
+        canvas.fill_rect(
+            x - half,
+            y - half,
+            x + half,
+            y + half,
+            blend(
+                BACKGROUND,
+                ZONE_COLORS[sprite.zone],
+                0.25 + 0.75 * clock_level,
+            ),
+        );
+
+        let border = (sprite.size * 0.08).max(1.0);
+        let polarizations: Vec<f64> = match &sprite.polarizations {
+            Some(views) => views
+                .iter()
+                .map(|view| view.get(sample).unwrap_or(0.0))
+                .collect(),
+            None => vec![],
+        };
+        let body = match polarizations.first() {
+            Some(polarization) => polarization_color(*polarization),
+            None => UNSTORED_CELL,
+        };
+        canvas.fill_rect(
+            x - half + border,
+            y - half + border,
+            x + half - border,
+            y + half - border,
+            body,
+        );
+
+        for (position, polarization, sign) in &sprite.dots {
+            let occupancy = polarizations
+                .get(*polarization)
+                .map_or(0.0, |value| ((1.0 + sign * value) / 2.0).clamp(0.0, 1.0));
+            canvas.fill_circle(
+                position[0],
+                position[1],
+                sprite.dot_radius,
+                blend(body, DOT, occupancy),
+            );
+        }
+    }
+}
+
+/// Renders the layout of a simulation over a range of samples and writes it
+/// as an animated GIF, an APNG or a sequence of PNG frames.
+#[tauri::command(async)]
+pub fn export_simulation_animation(
+    cache: State<'_, SimulationCache>,
+    filename: String,
+    output_filename: String,
+    options: AnimationOptions,
+) -> Result<(), String> {
+    let simulation = cache.access(&filename)?;
+    let simulation = simulation.source();
+    let (design, metadata) = (simulation.design(), simulation.metadata());
+    // GIF stores its dimensions in 16 bits
+    let max_size = u16::MAX as u32;
+    if options.width == 0
+        || options.height == 0
+        || options.width > max_size
+        || options.height > max_size
+    {
+        return Err("Invalid animation resolution".into());
+    }
+    if options.frame_rate <= 0.0 {
+        return Err("Frame rate must be positive".into());
+    }
+
+    let window = SampleWindow::new(options.start, options.end, None, metadata.num_samples)?;
+    if window.is_empty() {
+        return Err("Sample range is empty".into());
+    }
+    let frame_count = options
+        .frame_count
+        .unwrap_or(DEFAULT_FRAME_COUNT)
+        .clamp(1, window.end - window.start);
+    let samples: Vec<usize> = (0..frame_count)
+        .map(|frame| match frame_count {
+            1 => window.start,
+            n => window.start + frame * (window.end - window.start - 1) / (n - 1),
+        })
+        .collect();
+
+    let mut stored: HashMap<QCACellIndex, Vec<SignalView>> = HashMap::new();
+    for (i, cell) in metadata.stored_cells.iter().enumerate() {
+        let views = cell_signals(design, i, cell)?
+            .iter()
+            .map(|signal| simulation.signal_view(&signal.index))
+            .collect::<Result<Vec<_>, String>>()?;
+        stored.insert(*cell, views);
+    }
+
+    // Level of every clock zone at every frame, relative to the clock's range
+    let mut clock_levels = vec![vec![0.0; CLOCK_COUNT]; samples.len()];
+    for clock in 0..CLOCK_COUNT {
+        let view = simulation.signal_view(&SignalIndex {
+            kind: SignalKind::Clock,
+            index: clock,
+            subindex: None,
+        })?;
+        let (min, max) = view
+            .iter()
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), value| {
+                (min.min(value), max.max(value))
+            });
+        for (levels, sample) in clock_levels.iter_mut().zip(&samples) {
+            if let (true, Some(value)) = (max > min, view.get(*sample)) {
+                levels[clock] = (value - min) / (max - min);
+            }
+        }
+    }
+
+    let mut placed = vec![];
+    for (l, layer) in design.layers.iter().enumerate() {
+        if options.layer.map_or(false, |only| only != l) {
+            continue;
+        }
+        let architecture = design
+            .cell_architectures
+            .get(layer.cell_architecture_id.as_str())
+            .ok_or(format!(
+                "Cell architecture '{}' does not exist",
+                layer.cell_architecture_id
+            ))?;
+        for (c, cell) in layer.cells.iter().enumerate() {
+            placed.push((
+                QCACellIndex { layer: l, cell: c },
+                [cell.position[0], cell.position[1]],
+                architecture.side_length,
+            ));
+        }
+    }
+    if placed.is_empty() {
+        return Err("There are no cells to render".into());
+    }
+    let viewport = Viewport::fit(&placed, options.width, options.height);
+
+    let mut sprites = vec![];
+    for (index, position, size) in &placed {
+        let layer = &design.layers[index.layer];
+        let cell = &layer.cells[index.cell];
+        let architecture = &design.cell_architectures[layer.cell_architecture_id.as_str()];
+        let (sin, cos) = cell.rotation.to_radians().sin_cos();
+        let dots = architecture
+            .dot_positions
+            .iter()
+            .zip(dot_signs(architecture.dot_count as usize)?)
+            .map(|(dot, (polarization, sign))| {
+                let point = viewport.map([
+                    position[0] + dot[0] * cos - dot[1] * sin,
+                    position[1] + dot[0] * sin + dot[1] * cos,
+                ]);
+                (point, polarization, sign)
+            })
+            .collect();
+
+        sprites.push(CellSprite {
+            center: viewport.map(*position),
+            size: size * viewport.scale,
+            zone: cell_clock_zone(design, index)?,
+            dots,
+            dot_radius: (architecture.dot_diameter * viewport.scale / 2.0).max(1.0),
+            polarizations: stored.get(index).cloned(),
+        });
+    }
+
+    let mut canvas = Canvas::new(options.width, options.height);
+    let render_err = |err: String| format!("Failed to write animation: {}", err);
+    match options.format {
+        AnimationFormat::Gif => {
+            let file = File::create(&output_filename).map_err(|_err| "Failed to create file")?;
+            let mut encoder = gif::Encoder::new(
+                BufWriter::new(file),
+                options.width as u16,
+                options.height as u16,
+                &[],
+            )
+            .map_err(|err| render_err(err.to_string()))?;
+            encoder
+                .set_repeat(gif::Repeat::Infinite)
+                .map_err(|err| render_err(err.to_string()))?;
+            // GIF delays are in hundredths of a second
+            let delay = (100.0 / options.frame_rate).round().max(1.0) as u16;
+            for (sample, levels) in samples.iter().zip(&clock_levels) {
+                draw_frame(&mut canvas, &sprites, levels, *sample);
+                let mut frame = gif::Frame::from_rgba_speed(
+                    options.width as u16,
+                    options.height as u16,
+                    &mut canvas.pixels.clone(),
+                    10,
+                );
+                frame.delay = delay;
+                encoder
+                    .write_frame(&frame)
+                    .map_err(|err| render_err(err.to_string()))?;
+            }
+        }
+        AnimationFormat::Apng => {
+            let file = File::create(&output_filename).map_err(|_err| "Failed to create file")?;
+            let mut encoder =
+                png::Encoder::new(BufWriter::new(file), options.width, options.height);
+            encoder.set_color(png::ColorType::Rgba);
+            encoder.set_depth(png::BitDepth::Eight);
+            encoder
+                .set_animated(samples.len() as u32, 0)
+                .map_err(|err| render_err(err.to_string()))?;
+            let (numerator, denominator) = frame_delay(options.frame_rate);
+            encoder
+                .set_frame_delay(numerator, denominator)
+                .map_err(|err| render_err(err.to_string()))?;
+            let mut writer = encoder
+                .write_header()
+                .map_err(|err| render_err(err.to_string()))?;
+            for (sample, levels) in samples.iter().zip(&clock_levels) {
+                draw_frame(&mut canvas, &sprites, levels, *sample);
+                writer
+                    .write_image_data(&canvas.pixels)
+                    .map_err(|err| render_err(err.to_string()))?;
+            }
+            writer.finish().map_err(|err| render_err(err.to_string()))?;
+        }
+        AnimationFormat::PngSequence => {
+            let directory = Path::new(&output_filename);
+            std::fs::create_dir_all(directory).map_err(|_err| "Failed to create directory")?;
+            for (i, (sample, levels)) in samples.iter().zip(&clock_levels).enumerate() {
+                draw_frame(&mut canvas, &sprites, levels, *sample);
+                let file = File::create(directory.join(format!("frame_{:05}.png", i)))
+                    .map_err(|_err| "Failed to create file")?;
+                let mut encoder =
+                    png::Encoder::new(BufWriter::new(file), options.width, options.height);
+                encoder.set_color(png::ColorType::Rgba);
+                encoder.set_depth(png::BitDepth::Eight);
+                let mut writer = encoder
+                    .write_header()
+                    .map_err(|err| render_err(err.to_string()))?;
+                writer
+                    .write_image_data(&canvas.pixels)
+                    .map_err(|err| render_err(err.to_string()))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Frame duration as the fraction APNG stores it in.
+fn frame_delay(frame_rate: f64) -> (u16, u16) {
+    let denominator = 1000;
+    let numerator = (denominator as f64 / frame_rate)
+        .round()
+        .clamp(1.0, u16::MAX as f64);
+    (numerator as u16, denominator)
+}
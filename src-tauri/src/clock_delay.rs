@@ -0,0 +1,203 @@
+use crate::clocking::{cell_clock_zone, clock_holds, row_sample};
+use crate::digital::{digitize, DigitizeOptions, LogicValue};
+use crate::signal::{SignalIndex, SignalKind, SignalSource, SignalView};
+use crate::sim_cache::SimulationCache;
+use crate::truth_table::{is_output_cell, parse_cell_clock_delay, TruthTableSettings};
+use qca_core::objects::cell::QCACellIndex;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::ops::Range;
+use tauri::State;
+
+const DEFAULT_MAX_CLOCK_DELAY: usize = 4;
+/// Scores closer than this are considered a tie.
+const SCORE_EPSILON: f64 = 1e-9;
+
+#[derive(Serialize)]
+pub struct ClockDelaySuggestion {
+    cell: QCACellIndex,
+    /// Suggested delay in clock cycles, `None` if no delay could be scored.
+    delay: Option<usize>,
+    /// Score of the suggested delay, divided by the number of delays that
+    /// score equally well.
+    confidence: f64,
+    /// Per candidate delay, the fraction of rows in which the output is
+    /// consistent with the input combination it is paired with.
+    scores: Vec<Option<f64>>,
+}
+
+/// Digitised values of a cell, read once per clock cycle at the end of the
+/// hold phase of its clock zone.
+struct CellStream<'a> {
+    view: SignalView<'a>,
+    holds: &'a [Range<usize>],
+}
+
+impl<'a> CellStream<'a> {
+    fn value(&self, row: usize, delay: usize, options: &DigitizeOptions) -> Option<LogicValue> {
+        let sample = row_sample(self.holds, row, delay)?;
+        let polarization = self.view.get(sample)?;
+        Some(digitize(polarization, LogicValue::Unknown, options))
+    }
+}
+
+/// Fraction of rows whose output matches the most common output for the
+/// same input combination. Rows with unknown values are ignored.
+fn consistency_score(rows: &[(Vec<LogicValue>, LogicValue)]) -> Option<f64> {
+    let mut counts: HashMap<&Vec<LogicValue>, [usize; 2]> = HashMap::new();
+    let mut known = 0;
+    for (inputs, output) in rows {
+        if inputs.contains(&LogicValue::Unknown) {
+            continue;
+        }
+        let count = counts.entry(inputs).or_insert([0, 0]);
+        match output {
+            LogicValue::Zero => count[0] += 1,
+            LogicValue::One => count[1] += 1,
+            LogicValue::Unknown => continue,
+        }
+        known += 1;
+    }
+    match known {
+        0 | 1 => None,
+        _ => {
+            let consistent: usize = counts.values().map(|count| count[0].max(count[1])).sum();
+            Some(consistent as f64 / known as f64)
+        }
+    }
+}
+
+pub fn suggest_delays(
+    simulation: &dyn SignalSource,
+    settings: &TruthTableSettings,
+    max_delay: usize,
+) -> Result<Vec<ClockDelaySuggestion>, String> {
+    let (design, metadata) = (simulation.design(), simulation.metadata());
+    let options = DigitizeOptions {
+        logical_threshold: settings.logical_threshold,
+        value_threshold: settings.value_threshold,
+        hysteresis: 0.0,
+    };
+    let cell_clock_delay = parse_cell_clock_delay(&settings.cell_clock_delay)?;
+
+    let holds = clock_holds(simulation, settings.clock_threshold)?;
+    let stream = |cell: &QCACellIndex| -> Result<CellStream, String> {
+        let stored_index = metadata
+            .stored_cells
+            .iter()
+            .position(|stored| stored == cell)
+            .ok_or(format!("Cell {}-{} is not stored", cell.layer, cell.cell))?;
+        let index = SignalIndex {
+            kind: SignalKind::Cell,
+            index: stored_index,
+            subindex: None,
+        };
+        Ok(CellStream {
+            view: simulation.signal_view(&index)?,
+            holds: &holds[cell_clock_zone(design, cell)?],
+        })
+    };
+
+    let (outputs, inputs): (Vec<&QCACellIndex>, Vec<&QCACellIndex>) = settings
+        .cells
+        .iter()
+        .partition(|cell| is_output_cell(design, cell));
+    if inputs.is_empty() {
+        return Err("At least one input cell is needed to detect clock delays".into());
+    }
+    let input_streams = inputs
+        .iter()
+        .map(|cell| {
+            Ok((
+                stream(cell)?,
+                cell_clock_delay.get(*cell).copied().unwrap_or(0),
+            ))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    let num_rows = input_streams
+        .iter()
+        .map(|(stream, delay)| stream.holds.len().saturating_sub(*delay))
+        .min()
+        .unwrap_or(0);
+    let input_rows: Vec<Vec<LogicValue>> = (0..num_rows)
+        .map(|row| {
+            input_streams
+                .iter()
+                .map(|(stream, delay)| {
+                    stream
+                        .value(row, *delay, &options)
+                        .unwrap_or(LogicValue::Unknown)
+                })
+                .collect()
+        })
+        .collect();
+
+    let mut suggestions = vec![];
+    for cell in outputs {
+        let output = stream(cell)?;
+        let scores: Vec<Option<f64>> = (0..=max_delay)
+            .map(|delay| {
+                let rows: Vec<(Vec<LogicValue>, LogicValue)> = input_rows
+                    .iter()
+                    .enumerate()
+                    .map_while(|(row, inputs)| {
+                        output
+                            .value(row, delay, &options)
+                            .map(|value| (inputs.clone(), value))
+                    })
+                    .collect();
+                consistency_score(&rows)
+            })
+            .collect();
+
+        let best = scores
+            .iter()
+            .enumerate()
+            .filter_map(|(delay, score)| score.map(|score| (delay, score)))
+            .fold(
+                None,
+                |best: Option<(usize, f64)>, (delay, score)| match best {
+                    Some((_, best_score)) if best_score >= score - SCORE_EPSILON => best,
+                    _ => Some((delay, score)),
+                },
+            );
+        let (delay, confidence) = match best {
+            Some((delay, score)) => {
+                let ties = scores
+                    .iter()
+                    .flatten()
+                    .filter(|other| (score - **other).abs() < SCORE_EPSILON)
+                    .count();
+                (Some(delay), score / ties as f64)
+            }
+            None => (None, 0.0),
+        };
+
+        suggestions.push(ClockDelaySuggestion {
+            cell: *cell,
+            delay,
+            confidence,
+            scores,
+        });
+    }
+
+    Ok(suggestions)
+}
+
+/// Infers the clock delay of each output cell relative to the inputs by
+/// pairing the digitised input rows with output rows at every candidate
+/// delay and scoring how consistently the outputs follow the inputs.
+#[tauri::command(async)]
+pub fn suggest_clock_delays(
+    cache: State<'_, SimulationCache>,
+    filename: String,
+    settings: TruthTableSettings,
+    max_delay: Option<usize>,
+) -> Result<Vec<ClockDelaySuggestion>, String> {
+    let simulation = cache.access(&filename)?;
+    suggest_delays(
+        simulation.source(),
+        &settings,
+        max_delay.unwrap_or(DEFAULT_MAX_CLOCK_DELAY),
+    )
+}
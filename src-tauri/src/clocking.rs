@@ -0,0 +1,225 @@
+use crate::signal::{SignalIndex, SignalKind, SignalSource, SignalView, CLOCK_COUNT};
+use crate::sim_cache::SimulationCache;
+use qca_core::design::file::QCADesign;
+use qca_core::objects::cell::QCACellIndex;
+use serde::Serialize;
+use std::borrow::Borrow;
+use std::ops::Range;
+use tauri::State;
+
+/// Clock zone a cell is driven by, from its clock phase shift in degrees.
+pub fn cell_clock_zone(design: &QCADesign, cell: &QCACellIndex) -> Result<usize, String> {
+    let qca_cell = design
+        .layers
+        .get(cell.layer)
+        .and_then(|layer| layer.cells.get(cell.cell))
+        .ok_or(format!("Cell {}-{} does not exist", cell.layer, cell.cell))?;
+    Ok(((qca_cell.clock_phase_shift as f64 / 90.0).round() as i64).rem_euclid(4) as usize)
+}
+
+/// Runs of samples in which a clock holds, i.e. is within `clock_threshold`
+/// (relative to its amplitude) of its maximum. The clock is read twice, so
+/// it can be streamed from a [`SignalView`].
+pub fn hold_intervals<I>(clock: I, clock_threshold: f64) -> Vec<Range<usize>>
+where
+    I: IntoIterator + Clone,
+    I::Item: Borrow<f64>,
+{
+    let (min, max) = clock
+        .clone()
+        .into_iter()
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), value| {
+            (min.min(*value.borrow()), max.max(*value.borrow()))
+        });
+    let level = max - clock_threshold * (max - min);
+
+    let mut intervals = vec![];
+    let mut start = None;
+    let mut len = 0;
+    for (i, value) in clock.into_iter().enumerate() {
+        match (*value.borrow() >= level, start) {
+            (true, None) => start = Some(i),
+            (false, Some(s)) => {
+                intervals.push(s..i);
+                start = None;
+            }
+            _ => {}
+        }
+        len = i + 1;
+    }
+    if let Some(s) = start {
+        intervals.push(s..len);
+    }
+    intervals
+}
+
+/// Hold intervals of every clock zone.
+pub fn clock_holds(
+    simulation: &dyn SignalSource,
+    clock_threshold: f64,
+) -> Result<Vec<Vec<Range<usize>>>, String> {
+    (0..CLOCK_COUNT)
+        .map(|clock| {
+            let view = simulation.signal_view(&SignalIndex {
+                kind: SignalKind::Clock,
+                index: clock,
+                subindex: None,
+            })?;
+            Ok(hold_intervals(view.iter(), clock_threshold))
+        })
+        .collect()
+}
+
+/// The sample a truth table row of a cell is read at: the end of the hold
+/// phase `row + clock_delay` of the cell's clock zone.
+pub fn row_sample(holds: &[Range<usize>], row: usize, clock_delay: usize) -> Option<usize> {
+    holds
+        .get(row + clock_delay)
+        .map(|interval| interval.end - 1)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClockPhase {
+    Switch,
+    Hold,
+    Release,
+    Relax,
+}
+
+/// The phase of a clock at every sample. Holds and relaxes use the same
+/// levels as [`hold_intervals`]; in between, the clock switches after a
+/// relax and releases after a hold.
+pub fn clock_phases(clock: SignalView, clock_threshold: f64) -> Vec<ClockPhase> {
+    let min = clock.iter().fold(f64::INFINITY, f64::min);
+    let max = clock.iter().fold(f64::NEG_INFINITY, f64::max);
+    let high = max - clock_threshold * (max - min);
+    let low = min + clock_threshold * (max - min);
+
+    let mut phases: Vec<ClockPhase> = Vec::with_capacity(clock.len());
+    for (i, value) in clock.iter().enumerate() {
+        let phase = if value >= high {
+            ClockPhase::Hold
+        } else if value <= low {
+            ClockPhase::Relax
+        } else {
+            match phases.last() {
+                Some(ClockPhase::Relax) | Some(ClockPhase::Switch) => ClockPhase::Switch,
+                Some(ClockPhase::Hold) | Some(ClockPhase::Release) => ClockPhase::Release,
+                None => match clock.get(i + 1) {
+                    Some(next) if next < value => ClockPhase::Release,
+                    _ => ClockPhase::Switch,
+                },
+            }
+        };
+        phases.push(phase);
+    }
+    phases
+}
+
+/// Sample ranges of the clock cycles, each starting where clock zone 0
+/// begins to switch. Samples before the first switch form a leading,
+/// partial cycle.
+pub fn clock_cycles(zone_phases: &[ClockPhase]) -> Vec<Range<usize>> {
+    let mut cycles = vec![];
+    let mut start = 0;
+    for i in 1..zone_phases.len() {
+        if zone_phases[i] == ClockPhase::Switch && zone_phases[i - 1] != ClockPhase::Switch {
+            cycles.push(start..i);
+            start = i;
+        }
+    }
+    if start < zone_phases.len() {
+        cycles.push(start..zone_phases.len());
+    }
+    cycles
+}
+
+#[derive(Serialize)]
+pub struct ClockAnnotation {
+    /// Clock cycle of every sample.
+    cycles: Vec<usize>,
+    /// Phase of every clock zone at every sample.
+    phases: Vec<Vec<ClockPhase>>,
+    cycle_ranges: Vec<Range<usize>>,
+}
+
+/// Labels every sample with its clock cycle and the phase of each clock
+/// zone, so analyses agree on where cycles and phases begin.
+#[tauri::command(async)]
+pub fn annotate_clock_phases(
+    cache: State<'_, SimulationCache>,
+    filename: String,
+    clock_threshold: f64,
+) -> Result<ClockAnnotation, String> {
+    let simulation = cache.access(&filename)?;
+    let phases = (0..CLOCK_COUNT)
+        .map(|clock| {
+            let view = simulation.source().signal_view(&SignalIndex {
+                kind: SignalKind::Clock,
+                index: clock,
+                subindex: None,
+            })?;
+            Ok(clock_phases(view, clock_threshold))
+        })
+        .collect::<Result<Vec<Vec<ClockPhase>>, String>>()?;
+
+    let zone_phases = phases.first().ok_or("The simulation has no clocks")?;
+    let cycle_ranges = clock_cycles(zone_phases);
+    let mut cycles = vec![0; zone_phases.len()];
+    for (i, range) in cycle_ranges.iter().enumerate() {
+        cycles[range.clone()]
+            .iter_mut()
+            .for_each(|cycle| *cycle = i);
+    }
+
+    Ok(ClockAnnotation {
+        cycles,
+        phases,
+        cycle_ranges,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Trapezoidal clock: `relax` samples at 0, a 2 sample ramp, `hold`
+    /// samples at 1 and a 2 sample ramp down, repeated `periods` times.
+    fn clock(periods: usize, relax: usize, hold: usize) -> Vec<f64> {
+        let mut period = vec![0.0; relax];
+        period.extend([1.0 / 3.0, 2.0 / 3.0]);
+        period.extend(vec![1.0; hold]);
+        period.extend([2.0 / 3.0, 1.0 / 3.0]);
+        period.repeat(periods)
+    }
+
+    #[test]
+    fn finds_hold_intervals() {
+        let holds = hold_intervals(&clock(3, 4, 3), 0.1);
+        assert_eq!(holds, [6..9, 17..20, 28..31]);
+    }
+
+    #[test]
+    fn threshold_widens_holds() {
+        // Within 40% of the maximum also takes in the upper ramp samples
+        let holds = hold_intervals(&clock(2, 4, 3), 0.4);
+        assert_eq!(holds, [5..10, 16..21]);
+    }
+
+    #[test]
+    fn hold_at_the_end_is_closed() {
+        let samples = [0.0, 0.5, 1.0, 1.0];
+        assert_eq!(hold_intervals(&samples, 0.1), vec![2..4]);
+        assert!(hold_intervals(&[], 0.1).is_empty());
+    }
+
+    #[test]
+    fn row_sample_is_the_end_of_the_delayed_hold() {
+        let holds = vec![6..9, 17..20, 28..31];
+        assert_eq!(row_sample(&holds, 0, 0), Some(8));
+        assert_eq!(row_sample(&holds, 0, 2), Some(30));
+        assert_eq!(row_sample(&holds, 1, 1), Some(30));
+        assert_eq!(row_sample(&holds, 2, 1), None);
+    }
+}
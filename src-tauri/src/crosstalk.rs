@@ -0,0 +1,248 @@
+//! Electrostatic coupling between the cells of a design, from the dot
+//! geometry of their cell architectures.
+
+use crate::signal::dot_signs;
+use qca_core::design::file::QCADesign;
+use qca_core::objects::cell::QCACellIndex;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+
+/// Coulomb constant in N·m²/C².
+const COULOMB_CONSTANT: f64 = 8.987_551_792_3e9;
+const ELEMENTARY_CHARGE: f64 = 1.602_176_634e-19;
+/// Design coordinates are in nanometres.
+const DESIGN_UNIT: f64 = 1e-9;
+
+/// Offsets up to this many cell pitches count as adjacent.
+const NEIGHBOUR_DISTANCE: f64 = 1.25;
+const DIAGONAL_DISTANCE: f64 = 1.6;
+const WIRE_GAP_DISTANCE: f64 = 2.5;
+
+#[derive(Deserialize)]
+pub struct CrosstalkOptions {
+    /// Only cells whose centres are closer than this interact.
+    radius: f64,
+    /// GaAs by default.
+    #[serde(default = "default_relative_permittivity")]
+    relative_permittivity: f64,
+    /// Kink energy, relative to two adjacent cells, from which unintended
+    /// coupling is flagged.
+    #[serde(default = "default_coupling_threshold")]
+    coupling_threshold: f64,
+}
+
+fn default_relative_permittivity() -> f64 {
+    12.9
+}
+
+fn default_coupling_threshold() -> f64 {
+    0.1
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InteractionKind {
+    /// Adjacent cells of a wire, the intended coupling.
+    Neighbour,
+    Diagonal,
+    /// Cells across a one cell gap, e.g. between parallel wires.
+    WireGap,
+    /// Overlapping cells on different layers.
+    Crossover,
+    Distant,
+}
+
+#[derive(Serialize)]
+pub struct Interaction {
+    a: QCACellIndex,
+    b: QCACellIndex,
+    kind: InteractionKind,
+    distance: f64,
+    /// Energy cost of opposite over equal polarizations in eV. Negative
+    /// values favour inversion.
+    kink_energy: f64,
+    /// `kink_energy` relative to two adjacent cells of the same layer.
+    relative_strength: f64,
+    flagged: bool,
+}
+
+#[derive(Serialize)]
+pub struct CrosstalkReport {
+    /// Every interaction within the radius, strongest first, for the
+    /// design view overlay.
+    interactions: Vec<Interaction>,
+    /// Indices into `interactions` of the flagged ones, strongest first.
+    flagged: Vec<usize>,
+}
+
+/// Charged dots of a cell, with their charges in units of the elementary
+/// charge for a polarization of +1 in every polarization, so the dots of
+/// all polarizations of 8 dot cells couple.
+struct CellCharges {
+    index: QCACellIndex,
+    center: [f64; 3],
+    pitch: f64,
+    dots: Vec<([f64; 3], f64)>,
+}
+
+fn cell_charges(design: &QCADesign) -> Result<Vec<CellCharges>, String> {
+    let mut cells = vec![];
+    for (l, layer) in design.layers.iter().enumerate() {
+        let architecture = design
+            .cell_architectures
+            .get(layer.cell_architecture_id.as_str())
+            .ok_or(format!(
+                "Cell architecture '{}' does not exist",
+                layer.cell_architecture_id
+            ))?;
+        let signs = dot_signs(architecture.dot_count as usize)?;
+
+        for (c, cell) in layer.cells.iter().enumerate() {
+            let (sin, cos) = cell.rotation.to_radians().sin_cos();
+            let dots = architecture
+                .dot_positions
+                .iter()
+                .zip(&signs)
+                .map(|(dot, (_, sign))| {
+                    let position = [
+                        cell.position[0] + dot[0] * cos - dot[1] * sin,
+                        cell.position[1] + dot[0] * sin + dot[1] * cos,
+                        layer.z_position,
+                    ];
+                    (position, sign / 2.0)
+                })
+                .collect();
+
+            cells.push(CellCharges {
+                index: QCACellIndex { layer: l, cell: c },
+                center: [cell.position[0], cell.position[1], layer.z_position],
+                pitch: architecture.side_length,
+                dots,
+            });
+        }
+    }
+    Ok(cells)
+}
+
+fn distance(a: &[f64; 3], b: &[f64; 3]) -> f64 {
+    ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)).sqrt()
+}
+
+/// Kink energy in eV between two cells, with the dot charges neutralised so
+/// only the polarization dependent part remains.
+fn kink_energy(a: &CellCharges, b: &CellCharges, relative_permittivity: f64) -> f64 {
+    let mut coupling = 0.0;
+    for (position_a, charge_a) in &a.dots {
+        for (position_b, charge_b) in &b.dots {
+            let r = distance(position_a, position_b) * DESIGN_UNIT;
+            if r > 0.0 {
+                coupling += charge_a * charge_b / r;
+            }
+        }
+    }
+    // E(+1, +1) in eV; E(+1, -1) is its negation
+    let equal = COULOMB_CONSTANT * ELEMENTARY_CHARGE * coupling / relative_permittivity;
+    -2.0 * equal
+}
+
+fn classify(a: &CellCharges, b: &CellCharges) -> InteractionKind {
+    let pitch = a.pitch.max(b.pitch);
+    let (dx, dy) = (
+        (a.center[0] - b.center[0]).abs() / pitch,
+        (a.center[1] - b.center[1]).abs() / pitch,
+    );
+    let lateral = (dx * dx + dy * dy).sqrt();
+
+    if a.index.layer != b.index.layer {
+        return match lateral < 0.5 {
+            true => InteractionKind::Crossover,
+            false => InteractionKind::Distant,
+        };
+    }
+    let axis_aligned = dx.min(dy) < 0.5;
+    match (axis_aligned, lateral) {
+        (true, d) if d <= NEIGHBOUR_DISTANCE => InteractionKind::Neighbour,
+        (false, d) if d <= DIAGONAL_DISTANCE => InteractionKind::Diagonal,
+        (true, d) if d <= WIRE_GAP_DISTANCE => InteractionKind::WireGap,
+        _ => InteractionKind::Distant,
+    }
+}
+
+/// Kink energy of two adjacent cells in a straight wire, the reference for
+/// relative coupling strength.
+fn reference_energy(cell: &CellCharges, relative_permittivity: f64) -> f64 {
+    let neighbour = CellCharges {
+        index: cell.index,
+        center: [cell.center[0] + cell.pitch, cell.center[1], cell.center[2]],
+        pitch: cell.pitch,
+        dots: cell
+            .dots
+            .iter()
+            .map(|(position, charge)| {
+                (
+                    [position[0] + cell.pitch, position[1], position[2]],
+                    *charge,
+                )
+            })
+            .collect(),
+    };
+    kink_energy(cell, &neighbour, relative_permittivity)
+}
+
+#[tauri::command(async)]
+pub fn analyze_crosstalk(
+    qca_design: QCADesign,
+    options: CrosstalkOptions,
+) -> Result<CrosstalkReport, String> {
+    let cells = cell_charges(&qca_design)?;
+
+    let mut interactions = vec![];
+    for (i, a) in cells.iter().enumerate() {
+        let reference = reference_energy(a, options.relative_permittivity).abs();
+        for b in &cells[i + 1..] {
+            let center_distance = distance(&a.center, &b.center);
+            if center_distance > options.radius {
+                continue;
+            }
+
+            let kind = classify(a, b);
+            let kink_energy = kink_energy(a, b, options.relative_permittivity);
+            let relative_strength = match reference > 0.0 {
+                true => kink_energy.abs() / reference,
+                false => 0.0,
+            };
+            let flagged = match kind {
+                InteractionKind::Neighbour => false,
+                InteractionKind::Diagonal => true,
+                _ => relative_strength >= options.coupling_threshold,
+            };
+
+            interactions.push(Interaction {
+                a: a.index,
+                b: b.index,
+                kind,
+                distance: center_distance,
+                kink_energy,
+                relative_strength,
+                flagged,
+            });
+        }
+    }
+
+    interactions.sort_by(|a, b| {
+        b.relative_strength
+            .partial_cmp(&a.relative_strength)
+            .unwrap_or(Ordering::Equal)
+    });
+    let flagged = interactions
+        .iter()
+        .enumerate()
+        .filter(|(_, interaction)| interaction.flagged)
+        .map(|(i, _)| i)
+        .collect();
+
+    Ok(CrosstalkReport {
+        interactions,
+        flagged,
+    })
+}
@@ -0,0 +1,128 @@
+use crate::signal::{cell_signals, list_signals, SampleWindow, SignalDescriptor};
+use crate::signal_expr::{evaluate_derived, DerivedSignal};
+use crate::sim_cache::SimulationCache;
+use crate::timing::simulation_timing;
+use qca_core::objects::cell::QCACellIndex;
+use serde::Deserialize;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use tauri::State;
+
+#[derive(Deserialize)]
+pub struct CsvExportOptions {
+    /// Column delimiter, a comma by default. Use "\t" for TSV.
+    delimiter: Option<String>,
+    /// Number of decimal places, full precision by default.
+    precision: Option<usize>,
+    start: Option<usize>,
+    end: Option<usize>,
+    /// Keep every n-th sample.
+    stride: Option<usize>,
+    #[serde(default)]
+    include_time: bool,
+    /// Derived signals written after the simulated ones.
+    #[serde(default)]
+    derived: Vec<DerivedSignal>,
+}
+
+fn escape_field(field: &str, delimiter: &str) -> String {
+    if field.contains(delimiter) || field.contains('"') || field.contains('\n') {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn format_value(value: f64, precision: Option<usize>) -> String {
+    match precision {
+        Some(precision) => format!("{:.*}", precision, value),
+        None => value.to_string(),
+    }
+}
+
+#[tauri::command(async)]
+pub fn export_simulation_csv(
+    cache: State<'_, SimulationCache>,
+    filename: String,
+    output_filename: String,
+    cells: Option<Vec<QCACellIndex>>,
+    options: CsvExportOptions,
+) -> Result<(), String> {
+    let access = cache.access(&filename)?;
+    let simulation = access.source();
+    let (design, metadata) = (simulation.design(), simulation.metadata());
+
+    let delimiter = options.delimiter.unwrap_or(",".into());
+    let num_samples = metadata.num_samples;
+    let window = SampleWindow::new(options.start, options.end, options.stride, num_samples)?;
+
+    let signals: Vec<SignalDescriptor> = match cells {
+        Some(cells) => {
+            let mut signals: Vec<SignalDescriptor> = list_signals(design, metadata)?
+                .into_iter()
+                .filter(|signal| signal.cell.is_none())
+                .collect();
+            for cell in cells {
+                let stored_index = metadata
+                    .stored_cells
+                    .iter()
+                    .position(|stored| *stored == cell)
+                    .ok_or(format!(
+                        "Cell {}-{} is not stored in the simulation",
+                        cell.layer, cell.cell
+                    ))?;
+                signals.extend(cell_signals(design, stored_index, &cell)?);
+            }
+            signals
+        }
+        None => list_signals(design, metadata)?,
+    };
+    let derived = evaluate_derived(simulation, &options.derived, &window)?;
+    let views = signals
+        .iter()
+        .map(|signal| simulation.signal_view(&signal.index))
+        .collect::<Result<Vec<_>, String>>()?;
+
+    let timing = match options.include_time {
+        true => Some(simulation_timing(design, num_samples)?),
+        false => None,
+    };
+
+    let file = File::create(output_filename).map_err(|_err| "Failed to create file")?;
+    let mut writer = BufWriter::new(file);
+
+    let mut header = vec!["sample".to_string()];
+    if timing.is_some() {
+        header.push("time".into());
+    }
+    header.extend(
+        signals
+            .iter()
+            .map(|signal| signal.name.as_str())
+            .chain(options.derived.iter().map(|signal| signal.name.as_str()))
+            .map(|name| escape_field(name, &delimiter)),
+    );
+    writeln!(writer, "{}", header.join(&delimiter)).map_err(|_err| "Failed to write to file")?;
+
+    for (row_index, sample) in window.indices().enumerate() {
+        let mut row = vec![sample.to_string()];
+        if let Some(time) = timing.map(|timing| timing.sample_time(sample)) {
+            row.push(time.to_string());
+        }
+        let values = views.iter().map(|view| view.get(sample)).chain(
+            derived
+                .iter()
+                .map(|samples| samples.get(row_index).copied()),
+        );
+        for value in values {
+            row.push(match value {
+                Some(value) => format_value(value, options.precision),
+                None => String::new(),
+            });
+        }
+        writeln!(writer, "{}", row.join(&delimiter)).map_err(|_err| "Failed to write to file")?;
+    }
+
+    writer.flush().map_err(|_err| "Failed to write to file")?;
+    Ok(())
+}
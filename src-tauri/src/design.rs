@@ -1,14 +1,15 @@
+use crate::migration::migrate_design_file_contents;
 use std::{fs::File, io::Read};
 
 #[tauri::command]
 pub fn load_design_file(filename: String) -> Result<String, String> {
-    let file = File::open(filename).map_err(|_err| "File cannot be opened")?;
+    let file = File::open(&filename).map_err(|_err| "File cannot be opened")?;
     let mut buf_reader = std::io::BufReader::new(file);
     let mut file_contents = String::new();
     buf_reader
         .read_to_string(&mut file_contents)
         .map_err(|_err| "Failed to read file contents")?;
-    Ok(file_contents)
+    migrate_design_file_contents(&filename, file_contents)
 }
 
 #[tauri::command]
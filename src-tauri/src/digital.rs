@@ -0,0 +1,103 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum LogicValue {
+    Zero,
+    One,
+    Unknown,
+}
+
+impl LogicValue {
+    pub fn as_char(&self) -> char {
+        match self {
+            LogicValue::Zero => '0',
+            LogicValue::One => '1',
+            LogicValue::Unknown => 'x',
+        }
+    }
+}
+
+/// Thresholds for reading polarizations as logic levels: a polarization is
+/// a logic level only once its magnitude reaches `value_threshold`, and
+/// polarizations within `logical_threshold` of zero are never a level.
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct DigitizeOptions {
+    pub logical_threshold: f64,
+    pub value_threshold: f64,
+    /// Once at a level, the magnitude may drop this far below
+    /// `value_threshold` before the value becomes unknown again.
+    #[serde(default)]
+    pub hysteresis: f64,
+}
+
+pub fn digitize(polarization: f64, previous: LogicValue, options: &DigitizeOptions) -> LogicValue {
+    let level = if polarization > options.logical_threshold {
+        LogicValue::One
+    } else if polarization < -options.logical_threshold {
+        LogicValue::Zero
+    } else {
+        return LogicValue::Unknown;
+    };
+
+    let threshold = match level == previous {
+        true => options.value_threshold - options.hysteresis,
+        false => options.value_threshold,
+    };
+    match polarization.abs() >= threshold {
+        true => level,
+        false => LogicValue::Unknown,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OPTIONS: DigitizeOptions = DigitizeOptions {
+        logical_threshold: 0.1,
+        value_threshold: 0.6,
+        hysteresis: 0.2,
+    };
+
+    #[test]
+    fn digitizes_levels() {
+        let unknown = LogicValue::Unknown;
+        assert_eq!(digitize(0.9, unknown, &OPTIONS), LogicValue::One);
+        assert_eq!(digitize(-0.6, unknown, &OPTIONS), LogicValue::Zero);
+        assert_eq!(digitize(0.5, unknown, &OPTIONS), LogicValue::Unknown);
+        assert_eq!(
+            digitize(0.05, LogicValue::One, &OPTIONS),
+            LogicValue::Unknown
+        );
+    }
+
+    #[test]
+    fn hysteresis_keeps_the_previous_level() {
+        assert_eq!(digitize(0.5, LogicValue::One, &OPTIONS), LogicValue::One);
+        assert_eq!(digitize(-0.4, LogicValue::Zero, &OPTIONS), LogicValue::Zero);
+        assert_eq!(
+            digitize(0.3, LogicValue::One, &OPTIONS),
+            LogicValue::Unknown
+        );
+        // Switching to the other level takes the full threshold
+        assert_eq!(
+            digitize(-0.5, LogicValue::One, &OPTIONS),
+            LogicValue::Unknown
+        );
+        assert_eq!(digitize(-0.6, LogicValue::One, &OPTIONS), LogicValue::Zero);
+    }
+
+    #[test]
+    fn hysteresis_sequence() {
+        let polarizations = [0.0, 0.7, 0.5, 0.45, 0.3, 0.5, 0.65, -0.7, -0.45];
+        let mut value = LogicValue::Unknown;
+        let values: String = polarizations
+            .iter()
+            .map(|polarization| {
+                value = digitize(*polarization, value, &OPTIONS);
+                value.as_char()
+            })
+            .collect();
+        assert_eq!(values, "x111xx100");
+    }
+}
@@ -0,0 +1,168 @@
+use crate::signal::{list_signals, SampleWindow, SignalIndex, SignalView};
+use crate::sim_cache::SimulationCache;
+use serde::{Deserialize, Serialize};
+use tauri::State;
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DownsampleMethod {
+    MinMax,
+    Lttb,
+}
+
+/// Per bucket minimum and maximum, with the original sample indices at which
+/// they occur, so no spike is lost when drawing.
+#[derive(Default, Serialize)]
+pub struct MinMaxEnvelope {
+    bucket_start: Vec<usize>,
+    min: Vec<f64>,
+    min_index: Vec<usize>,
+    max: Vec<f64>,
+    max_index: Vec<usize>,
+}
+
+/// Points chosen by Largest-Triangle-Three-Buckets.
+#[derive(Default, Serialize)]
+pub struct LttbPoints {
+    indices: Vec<usize>,
+    values: Vec<f64>,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "method", rename_all = "snake_case")]
+pub enum DownsampledData {
+    MinMax(MinMaxEnvelope),
+    Lttb(LttbPoints),
+}
+
+#[derive(Serialize)]
+pub struct DownsampledSignal {
+    index: SignalIndex,
+    name: String,
+    data: DownsampledData,
+}
+
+fn min_max_envelope(samples: SignalView, first_index: usize, buckets: usize) -> MinMaxEnvelope {
+    let mut envelope = MinMaxEnvelope::default();
+    let len = samples.len();
+    if len == 0 || buckets == 0 {
+        return envelope;
+    }
+    let value = |i: usize| samples.get(i).unwrap_or(f64::NAN);
+
+    let buckets = buckets.min(len);
+    for bucket in 0..buckets {
+        let start = bucket * len / buckets;
+        let end = (bucket + 1) * len / buckets;
+
+        let (mut min_i, mut max_i) = (start, start);
+        let (mut min, mut max) = (value(start), value(start));
+        for i in start..end {
+            let sample = value(i);
+            if sample < min {
+                (min_i, min) = (i, sample);
+            }
+            if sample > max {
+                (max_i, max) = (i, sample);
+            }
+        }
+
+        envelope.bucket_start.push(first_index + start);
+        envelope.min.push(min);
+        envelope.min_index.push(first_index + min_i);
+        envelope.max.push(max);
+        envelope.max_index.push(first_index + max_i);
+    }
+
+    envelope
+}
+
+fn lttb(samples: SignalView, first_index: usize, threshold: usize) -> LttbPoints {
+    let mut points = LttbPoints::default();
+    let len = samples.len();
+    if threshold >= len || threshold < 3 {
+        points.indices = (first_index..first_index + len).collect();
+        points.values = samples.to_vec();
+        return points;
+    }
+    let value = |i: usize| samples.get(i).unwrap_or(f64::NAN);
+
+    let bucket_size = (len - 2) as f64 / (threshold - 2) as f64;
+    let mut selected = 0;
+    points.indices.push(first_index);
+    points.values.push(value(0));
+
+    for bucket in 0..threshold - 2 {
+        let start = (bucket as f64 * bucket_size) as usize + 1;
+        let end = ((bucket + 1) as f64 * bucket_size) as usize + 1;
+
+        let next_start = end;
+        let next_end = (((bucket + 2) as f64 * bucket_size) as usize + 1).min(len);
+        let next_len = (next_end - next_start) as f64;
+        let avg_x = (next_start..next_end).sum::<usize>() as f64 / next_len;
+        let avg_y = (next_start..next_end).map(value).sum::<f64>() / next_len;
+
+        let (ax, ay) = (selected as f64, value(selected));
+        let mut max_area = -1.0;
+        for i in start..end {
+            let area = ((ax - avg_x) * (value(i) - ay) - (ax - i as f64) * (avg_y - ay)).abs();
+            if area > max_area {
+                max_area = area;
+                selected = i;
+            }
+        }
+
+        points.indices.push(first_index + selected);
+        points.values.push(value(selected));
+    }
+
+    points.indices.push(first_index + len - 1);
+    points.values.push(value(len - 1));
+
+    points
+}
+
+#[tauri::command(async)]
+pub fn downsample_signals(
+    cache: State<'_, SimulationCache>,
+    filename: String,
+    signals: Vec<SignalIndex>,
+    width: usize,
+    method: DownsampleMethod,
+    start: Option<usize>,
+    end: Option<usize>,
+) -> Result<Vec<DownsampledSignal>, String> {
+    let simulation = cache.access(&filename)?;
+    let simulation = simulation.source();
+    let (design, metadata) = (simulation.design(), simulation.metadata());
+
+    let window = SampleWindow::new(start, end, None, metadata.num_samples)?;
+    if window.is_empty() {
+        return Err("Sample window is empty".into());
+    }
+    let descriptors = list_signals(design, metadata)?;
+
+    signals
+        .iter()
+        .map(|index| {
+            let descriptor = descriptors
+                .iter()
+                .find(|descriptor| descriptor.index == *index)
+                .ok_or("Signal does not exist")?;
+            let samples = simulation.signal_view(index)?.window(&window);
+
+            Ok(DownsampledSignal {
+                index: *index,
+                name: descriptor.name.clone(),
+                data: match method {
+                    DownsampleMethod::MinMax => {
+                        DownsampledData::MinMax(min_max_envelope(samples, window.start, width))
+                    }
+                    DownsampleMethod::Lttb => {
+                        DownsampledData::Lttb(lttb(samples, window.start, width))
+                    }
+                },
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,328 @@
+use crate::clocking::clock_holds;
+use crate::signal::dot_signs;
+use crate::sim_cache::LoadedSimulation;
+use crate::simulation::simulate_design;
+use crate::truth_table::{compute_truth_table, TruthTableSettings};
+use crate::verification::{
+    parse_expectations, verify_table, OutputExpectation, TruthTableVerification,
+};
+use qca_core::design::file::QCADesign;
+use qca_core::objects::cell::{CellType, QCACellIndex};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tauri::{AppHandle, Emitter, State};
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FaultKind {
+    Missing,
+    Displaced,
+    Rotated,
+    StuckPositive,
+    StuckNegative,
+}
+
+const FAULT_KINDS: [FaultKind; 5] = [
+    FaultKind::Missing,
+    FaultKind::Displaced,
+    FaultKind::Rotated,
+    FaultKind::StuckPositive,
+    FaultKind::StuckNegative,
+];
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct FaultOptions {
+    /// Offset of a displaced cell along x, in nm.
+    displacement: f64,
+    /// Rotation of a rotated cell, in degrees.
+    rotation: f64,
+    /// Cells to inject faults into, all normal cells when not given.
+    cells: Option<Vec<QCACellIndex>>,
+}
+
+impl Default for FaultOptions {
+    fn default() -> Self {
+        FaultOptions {
+            displacement: 2.0,
+            rotation: 45.0,
+            cells: None,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct FaultResult {
+    kind: FaultKind,
+    /// Whether the variant still matches the expected truth table, `None`
+    /// if it could not be simulated or verified.
+    passed: Option<bool>,
+    mismatched_rows: Vec<usize>,
+    /// Why the variant could not be simulated or verified.
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct CellCriticality {
+    cell: QCACellIndex,
+    faults: Vec<FaultResult>,
+    /// Fraction of the verified faults that break the design, `None` if no
+    /// fault could be verified.
+    criticality: Option<f64>,
+    /// Number of faults that could not be simulated or verified.
+    failed: usize,
+}
+
+/// Lets a running fault analysis be cancelled.
+pub struct FaultAnalysisState {
+    stop: AtomicBool,
+}
+
+impl FaultAnalysisState {
+    pub fn new() -> FaultAnalysisState {
+        FaultAnalysisState {
+            stop: AtomicBool::new(false),
+        }
+    }
+}
+
+impl Default for FaultAnalysisState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Clone, Serialize)]
+struct FaultAnalysisProgress {
+    current: usize,
+    total: usize,
+}
+
+/// Moves keys of cells after a removed one down by one within its layer.
+/// Returns `None` for the removed cell itself.
+fn remap_index(cell: &QCACellIndex, removed: &QCACellIndex) -> Option<QCACellIndex> {
+    if cell.layer != removed.layer || cell.cell < removed.cell {
+        return Some(*cell);
+    }
+    match cell.cell == removed.cell {
+        true => None,
+        false => Some(QCACellIndex {
+            layer: cell.layer,
+            cell: cell.cell - 1,
+        }),
+    }
+}
+
+fn remap_keys<T: Clone>(
+    map: &HashMap<String, T>,
+    removed: &QCACellIndex,
+) -> Result<HashMap<String, T>, String> {
+    let mut remapped = HashMap::new();
+    for (k, v) in map {
+        let cell =
+            QCACellIndex::from_str(k).map_err(|_err| format!("Invalid cell index '{}'", k))?;
+        if let Some(cell) = remap_index(&cell, removed) {
+            remapped.insert(format!("{}-{}", cell.layer, cell.cell), v.clone());
+        }
+    }
+    Ok(remapped)
+}
+
+/// Builds the faulty design along with the truth table settings and
+/// expectations adjusted to its cell indices.
+fn inject_fault(
+    design: &QCADesign,
+    settings: &TruthTableSettings,
+    expectations: &HashMap<String, OutputExpectation>,
+    cell: &QCACellIndex,
+    kind: FaultKind,
+    options: &FaultOptions,
+) -> Result<
+    (
+        QCADesign,
+        TruthTableSettings,
+        HashMap<String, OutputExpectation>,
+    ),
+    String,
+> {
+    let mut design = design.clone();
+    let mut settings = settings.clone();
+    let mut expectations = expectations.clone();
+    let layer = design
+        .layers
+        .get_mut(cell.layer)
+        .ok_or(format!("Layer {} does not exist", cell.layer))?;
+    if cell.cell >= layer.cells.len() {
+        return Err(format!("Cell {}-{} does not exist", cell.layer, cell.cell));
+    }
+
+    match kind {
+        FaultKind::Missing => {
+            if settings.cells.contains(cell) {
+                return Err("Cell is part of the truth table".into());
+            }
+            layer.cells.remove(cell.cell);
+            settings.cells = settings
+                .cells
+                .iter()
+                .filter_map(|c| remap_index(c, cell))
+                .collect();
+            settings.cell_clock_delay = remap_keys(&settings.cell_clock_delay, cell)?;
+            expectations = remap_keys(&expectations, cell)?;
+        }
+        FaultKind::Displaced => layer.cells[cell.cell].position[0] += options.displacement,
+        FaultKind::Rotated => layer.cells[cell.cell].rotation += options.rotation,
+        FaultKind::StuckPositive | FaultKind::StuckNegative => {
+            let architecture = design
+                .cell_architectures
+                .get(&layer.cell_architecture_id)
+                .ok_or("Layer has an unknown cell architecture")?;
+            let sign = match kind {
+                FaultKind::StuckPositive => 1.0,
+                _ => -1.0,
+            };
+            let target = &mut layer.cells[cell.cell];
+            target.typ = CellType::Fixed;
+            target.dot_probability_distribution = dot_signs(architecture.dot_count as usize)?
+                .iter()
+                .map(
+                    |(polarization, dot_sign)| match *polarization == 0 && dot_sign * sign > 0.0 {
+                        true => 1.0,
+                        false => 0.0,
+                    },
+                )
+                .collect();
+        }
+    }
+    Ok((design, settings, expectations))
+}
+
+fn simulate_and_verify(
+    design: QCADesign,
+    settings: &TruthTableSettings,
+    expectations: &HashMap<String, OutputExpectation>,
+    stop: &AtomicBool,
+) -> Result<TruthTableVerification, String> {
+    let data = simulate_design(&design, stop, |_| {})?;
+    let simulation = LoadedSimulation { design, data };
+    let holds = clock_holds(&simulation, settings.clock_threshold)?;
+    let table = compute_truth_table(&simulation, settings, &holds)?;
+    verify_table(&table, &holds, &parse_expectations(expectations)?)
+}
+
+/// Injects single defects into every target cell of a working design,
+/// re-simulates each variant and reports which defects break the expected
+/// truth table. Runs until done or cancelled with
+/// [`cancel_fault_analysis`].
+#[tauri::command(async)]
+pub fn analyze_fault_criticality(
+    app: AppHandle,
+    state: State<'_, FaultAnalysisState>,
+    qca_design: QCADesign,
+    settings: TruthTableSettings,
+    expectations: HashMap<String, OutputExpectation>,
+    options: Option<FaultOptions>,
+) -> Result<Vec<CellCriticality>, String> {
+    let options = options.unwrap_or_default();
+    if expectations.is_empty() {
+        return Err("No expected outputs are given".into());
+    }
+
+    let stop = &state.stop;
+    stop.store(false, Ordering::Relaxed);
+    let cancelled = || Err("Fault analysis was cancelled".into());
+
+    let baseline = simulate_and_verify(qca_design.clone(), &settings, &expectations, stop)?;
+    if !baseline.passed() {
+        return Err(format!(
+            "The design does not match its expected truth table: rows {:?} differ, rows {:?} are unknown",
+            baseline.mismatched_rows(),
+            baseline.unknown_rows()
+        ));
+    }
+
+    let cells = match &options.cells {
+        Some(cells) => cells.clone(),
+        None => qca_design
+            .layers
+            .iter()
+            .enumerate()
+            .flat_map(|(l, layer)| {
+                layer
+                    .cells
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, cell)| cell.typ == CellType::Normal)
+                    .map(move |(c, _)| QCACellIndex { layer: l, cell: c })
+            })
+            .collect(),
+    };
+
+    let total = cells.len() * FAULT_KINDS.len();
+    let mut report = vec![];
+    for (i, cell) in cells.iter().enumerate() {
+        let mut faults = vec![];
+        for (j, kind) in FAULT_KINDS.iter().enumerate() {
+            if stop.load(Ordering::Relaxed) {
+                return cancelled();
+            }
+            let _ = app.emit(
+                "faultAnalysisProgress",
+                FaultAnalysisProgress {
+                    current: i * FAULT_KINDS.len() + j,
+                    total,
+                },
+            );
+            let result = inject_fault(&qca_design, &settings, &expectations, cell, *kind, &options)
+                .and_then(|(design, settings, expectations)| {
+                    simulate_and_verify(design, &settings, &expectations, stop)
+                });
+            if stop.load(Ordering::Relaxed) {
+                return cancelled();
+            }
+            faults.push(match result {
+                Ok(verification) => FaultResult {
+                    kind: *kind,
+                    passed: Some(verification.passed()),
+                    mismatched_rows: verification.mismatched_rows(),
+                    error: None,
+                },
+                Err(err) => FaultResult {
+                    kind: *kind,
+                    passed: None,
+                    mismatched_rows: vec![],
+                    error: Some(err),
+                },
+            });
+        }
+
+        let verified = faults.iter().filter(|fault| fault.passed.is_some()).count();
+        let broken = faults
+            .iter()
+            .filter(|fault| fault.passed == Some(false))
+            .count();
+        report.push(CellCriticality {
+            cell: *cell,
+            criticality: (verified > 0).then(|| broken as f64 / verified as f64),
+            failed: faults.len() - verified,
+            faults,
+        });
+    }
+    let _ = app.emit(
+        "faultAnalysisProgress",
+        FaultAnalysisProgress {
+            current: total,
+            total,
+        },
+    );
+
+    Ok(report)
+}
+
+/// Stops a running fault analysis after the variant being simulated.
+#[tauri::command]
+pub fn cancel_fault_analysis(state: State<'_, FaultAnalysisState>) {
+    state.stop.store(true, Ordering::Relaxed);
+}
@@ -0,0 +1,330 @@
+//! Boolean expressions over cell labels, used to describe the function an
+//! output cell is expected to implement.
+//!
+//! Operators are `NOT`/`!`/`~`, `AND`/`&`, `XOR`/`^` and `OR`/`|`, in order
+//! of precedence, plus the functions `AND(..)`, `OR(..)`, `NOT(..)`,
+//! `XOR(..)` and `MAJ(..)`. Labels containing spaces or operator characters
+//! can be written in double quotes, e.g. `MAJ(A, B, "Cell 0-3")`.
+
+use std::collections::HashMap;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum LogicExpr {
+    Constant(bool),
+    Input(String),
+    Not(Box<LogicExpr>),
+    And(Vec<LogicExpr>),
+    Or(Vec<LogicExpr>),
+    Xor(Vec<LogicExpr>),
+    Maj(Vec<LogicExpr>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Ident(String),
+    Not,
+    And,
+    Or,
+    Xor,
+    Maj,
+    LParen,
+    RParen,
+    Comma,
+}
+
+fn keyword(ident: &str) -> Option<Token> {
+    match ident.to_uppercase().as_str() {
+        "NOT" => Some(Token::Not),
+        "AND" => Some(Token::And),
+        "OR" => Some(Token::Or),
+        "XOR" => Some(Token::Xor),
+        "MAJ" => Some(Token::Maj),
+        _ => None,
+    }
+}
+
+fn tokenize(expression: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut chars = expression.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' | ')' | ',' | '!' | '~' | '&' | '|' | '^' => {
+                chars.next();
+                tokens.push(match c {
+                    '(' => Token::LParen,
+                    ')' => Token::RParen,
+                    ',' => Token::Comma,
+                    '!' | '~' => Token::Not,
+                    '&' => Token::And,
+                    '|' => Token::Or,
+                    _ => Token::Xor,
+                });
+            }
+            '"' => {
+                chars.next();
+                let mut ident = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => ident.push(c),
+                        None => return Err("Unterminated quoted label".into()),
+                    }
+                }
+                tokens.push(Token::Ident(ident));
+            }
+            c if c.is_alphanumeric() || c == '_' || c == '.' || c == '-' => {
+                let mut ident = String::new();
+                while let Some(&c) = chars.peek() {
+                    if !(c.is_alphanumeric() || c == '_' || c == '.' || c == '-') {
+                        break;
+                    }
+                    ident.push(c);
+                    chars.next();
+                }
+                tokens.push(keyword(&ident).unwrap_or(Token::Ident(ident)));
+            }
+            c => return Err(format!("Unexpected character '{}'", c)),
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn expect(&mut self, token: Token) -> Result<(), String> {
+        match self.next() {
+            Some(next) if next == token => Ok(()),
+            Some(next) => Err(format!("Expected {:?}, found {:?}", token, next)),
+            None => Err(format!("Expected {:?}, found end of expression", token)),
+        }
+    }
+
+    fn binary(
+        &mut self,
+        operator: Token,
+        operand: fn(&mut Parser) -> Result<LogicExpr, String>,
+        combine: fn(Vec<LogicExpr>) -> LogicExpr,
+    ) -> Result<LogicExpr, String> {
+        let mut operands = vec![operand(self)?];
+        while self.peek() == Some(&operator) {
+            self.next();
+            operands.push(operand(self)?);
+        }
+        Ok(match operands.len() {
+            1 => operands.remove(0),
+            _ => combine(operands),
+        })
+    }
+
+    fn or(&mut self) -> Result<LogicExpr, String> {
+        self.binary(Token::Or, Parser::xor, LogicExpr::Or)
+    }
+
+    fn xor(&mut self) -> Result<LogicExpr, String> {
+        self.binary(Token::Xor, Parser::and, LogicExpr::Xor)
+    }
+
+    fn and(&mut self) -> Result<LogicExpr, String> {
+        self.binary(Token::And, Parser::not, LogicExpr::And)
+    }
+
+    fn not(&mut self) -> Result<LogicExpr, String> {
+        if self.peek() == Some(&Token::Not) {
+            self.next();
+            // NOT(a) is handled like a parenthesized operand
+            return Ok(LogicExpr::Not(Box::new(self.not()?)));
+        }
+        self.primary()
+    }
+
+    fn arguments(&mut self) -> Result<Vec<LogicExpr>, String> {
+        self.expect(Token::LParen)?;
+        let mut arguments = vec![self.or()?];
+        while self.peek() == Some(&Token::Comma) {
+            self.next();
+            arguments.push(self.or()?);
+        }
+        self.expect(Token::RParen)?;
+        Ok(arguments)
+    }
+
+    fn primary(&mut self) -> Result<LogicExpr, String> {
+        match self.next() {
+            Some(Token::LParen) => {
+                let expr = self.or()?;
+                self.expect(Token::RParen)?;
+                Ok(expr)
+            }
+            Some(function @ (Token::And | Token::Or | Token::Xor | Token::Maj)) => {
+                let arguments = self.arguments()?;
+                Ok(match function {
+                    Token::And => LogicExpr::And(arguments),
+                    Token::Or => LogicExpr::Or(arguments),
+                    Token::Xor => LogicExpr::Xor(arguments),
+                    _ => {
+                        if arguments.len() % 2 == 0 {
+                            return Err("MAJ needs an odd number of arguments".into());
+                        }
+                        LogicExpr::Maj(arguments)
+                    }
+                })
+            }
+            Some(Token::Ident(ident)) => Ok(match ident.as_str() {
+                "0" => LogicExpr::Constant(false),
+                "1" => LogicExpr::Constant(true),
+                _ => LogicExpr::Input(ident),
+            }),
+            Some(token) => Err(format!("Unexpected {:?}", token)),
+            None => Err("Unexpected end of expression".into()),
+        }
+    }
+}
+
+impl LogicExpr {
+    pub fn parse(expression: &str) -> Result<LogicExpr, String> {
+        let mut parser = Parser {
+            tokens: tokenize(expression)?,
+            pos: 0,
+        };
+        let expr = parser.or()?;
+        match parser.next() {
+            None => Ok(expr),
+            Some(token) => Err(format!("Unexpected {:?}", token)),
+        }
+    }
+
+    /// Evaluates the expression. Returns `None` if an input is unknown.
+    pub fn eval(&self, inputs: &HashMap<String, Option<bool>>) -> Result<Option<bool>, String> {
+        let eval_all = |operands: &Vec<LogicExpr>| {
+            operands
+                .iter()
+                .map(|operand| operand.eval(inputs))
+                .collect::<Result<Option<Vec<bool>>, String>>()
+        };
+
+        Ok(match self {
+            LogicExpr::Constant(value) => Some(*value),
+            LogicExpr::Input(label) => *inputs
+                .get(label)
+                .ok_or(format!("Unknown input '{}'", label))?,
+            LogicExpr::Not(operand) => operand.eval(inputs)?.map(|value| !value),
+            LogicExpr::And(operands) => eval_all(operands)?.map(|v| v.iter().all(|v| *v)),
+            LogicExpr::Or(operands) => eval_all(operands)?.map(|v| v.iter().any(|v| *v)),
+            LogicExpr::Xor(operands) => {
+                eval_all(operands)?.map(|v| v.iter().filter(|v| **v).count() % 2 == 1)
+            }
+            LogicExpr::Maj(operands) => {
+                eval_all(operands)?.map(|v| v.iter().filter(|v| **v).count() * 2 > v.len())
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input(label: &str) -> LogicExpr {
+        LogicExpr::Input(label.to_string())
+    }
+
+    fn eval(expression: &str, values: &[(&str, Option<bool>)]) -> Option<bool> {
+        let inputs = values
+            .iter()
+            .map(|(label, value)| (label.to_string(), *value))
+            .collect();
+        LogicExpr::parse(expression).unwrap().eval(&inputs).unwrap()
+    }
+
+    #[test]
+    fn operators_bind_by_precedence() {
+        assert_eq!(
+            LogicExpr::parse("A | !B & C ^ D").unwrap(),
+            LogicExpr::Or(vec![
+                input("A"),
+                LogicExpr::Xor(vec![
+                    LogicExpr::And(vec![LogicExpr::Not(Box::new(input("B"))), input("C")]),
+                    input("D"),
+                ]),
+            ])
+        );
+        assert_eq!(
+            LogicExpr::parse("(A or b) AND not c").unwrap(),
+            LogicExpr::And(vec![
+                LogicExpr::Or(vec![input("A"), input("b")]),
+                LogicExpr::Not(Box::new(input("c"))),
+            ])
+        );
+    }
+
+    #[test]
+    fn parses_functions_and_labels() {
+        assert_eq!(
+            LogicExpr::parse("MAJ(A, ~B, \"Cell 0-3\")").unwrap(),
+            LogicExpr::Maj(vec![
+                input("A"),
+                LogicExpr::Not(Box::new(input("B"))),
+                input("Cell 0-3"),
+            ])
+        );
+        assert_eq!(
+            LogicExpr::parse("xor(in_1, 1)").unwrap(),
+            LogicExpr::Xor(vec![input("in_1"), LogicExpr::Constant(true)])
+        );
+        assert_eq!(
+            LogicExpr::parse("NOT(0)").unwrap(),
+            LogicExpr::Not(Box::new(LogicExpr::Constant(false)))
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_expressions() {
+        for expression in [
+            "",
+            "A &",
+            "(A | B",
+            "A B",
+            "MAJ(A, B)",
+            "AND()",
+            "\"A",
+            "A $ B",
+        ] {
+            assert!(LogicExpr::parse(expression).is_err(), "{}", expression);
+        }
+    }
+
+    #[test]
+    fn evaluates_expressions() {
+        let inputs = [("A", Some(true)), ("B", Some(false)), ("C", Some(true))];
+        assert_eq!(eval("MAJ(A, B, C)", &inputs), Some(true));
+        assert_eq!(eval("A ^ C", &inputs), Some(false));
+        assert_eq!(eval("XOR(A, B, C)", &inputs), Some(false));
+        assert_eq!(eval("!A | B", &inputs), Some(false));
+        assert_eq!(eval("A & (B | C)", &inputs), Some(true));
+        assert_eq!(eval("A & X", &[("A", Some(true)), ("X", None)]), None);
+
+        let error = LogicExpr::parse("A & Y")
+            .unwrap()
+            .eval(&HashMap::from([("A".to_string(), Some(true))]));
+        assert!(error.is_err());
+    }
+}
@@ -3,9 +3,9 @@
 
 use serde::Serialize;
 use std::sync::Mutex;
-use tauri::async_runtime::spawn;
-use tauri::http::{header, Response, StatusCode};
-use tauri::{Emitter, Manager};
+use tauri::async_runtime::{spawn, spawn_blocking};
+use tauri::http::{header, Request, Response, StatusCode};
+use tauri::{AppHandle, Emitter, Manager};
 
 #[derive(Serialize)]
 struct BuildInfo {
@@ -21,10 +21,59 @@ mod window_menu;
 use window_menu::create_menu_bar;
 
 mod analysis;
+mod animation;
+mod clock_delay;
+mod clocking;
+mod crosstalk;
+mod csv_export;
+mod digital;
+mod downsample;
+mod fault_injection;
+mod logic_expr;
+mod migration;
+mod npz_export;
+mod propagation;
+mod signal;
+mod signal_expr;
+mod sim_cache;
+mod sim_diff;
+mod sim_format;
+mod sim_frame;
+mod sim_header;
+mod sim_merge;
+mod sim_mmap;
 mod simulation;
+mod snapshot;
+mod statistics;
+mod ternary;
+mod thresholds;
+mod timing;
+mod truth_table;
+mod vcd_export;
+mod verification;
 
 use analysis::*;
+use animation::*;
+use clock_delay::*;
+use clocking::*;
+use crosstalk::*;
+use csv_export::*;
+use downsample::*;
+use fault_injection::*;
+use npz_export::*;
+use propagation::*;
+use signal_expr::*;
+use sim_cache::*;
+use sim_diff::*;
+use sim_header::*;
+use sim_merge::*;
 use simulation::*;
+use snapshot::*;
+use statistics::*;
+use thresholds::*;
+use truth_table::*;
+use vcd_export::*;
+use verification::*;
 
 mod startup;
 use startup::*;
@@ -35,7 +84,31 @@ use design::*;
 mod log;
 use log::*;
 
+/// Release builds on Windows start without a console, so command line
+/// modes write to the console of the shell they were started from.
+#[cfg(windows)]
+fn attach_parent_console() {
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn AttachConsole(process_id: u32) -> i32;
+    }
+    const ATTACH_PARENT_PROCESS: u32 = u32::MAX;
+    // Fails when started without a console, output is then discarded
+    unsafe {
+        AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
+#[cfg(not(windows))]
+fn attach_parent_console() {}
+
 fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("diff-sim") {
+        attach_parent_console();
+        std::process::exit(run_diff_cli(&args[2..]));
+    }
+
     tauri::Builder::default()
         .plugin(tauri_plugin_notification::init())
         .plugin(tauri_plugin_store::Builder::new().build())
@@ -60,6 +133,8 @@ fn main() {
             Ok(())
         })
         .manage(Mutex::new(StartupState::new()))
+        .manage(SimulationCache::new())
+        .manage(FaultAnalysisState::new())
         .invoke_handler(tauri::generate_handler![
             get_build_info,
             get_sim_version,
@@ -68,7 +143,31 @@ fn main() {
             load_design_file,
             save_design_file,
             load_simulation_file,
+            inspect_simulation_file,
+            load_simulation_window,
             calculate_truth_table,
+            export_truth_table,
+            verify_truth_table,
+            suggest_clock_delays,
+            annotate_clock_phases,
+            suggest_thresholds,
+            signal_statistics,
+            measure_propagation_delay,
+            analyze_crosstalk,
+            analyze_fault_criticality,
+            cancel_fault_analysis,
+            diff_simulations,
+            merge_simulations,
+            polarization_snapshot,
+            evaluate_signal_expression,
+            downsample_signals,
+            export_simulation_csv,
+            export_simulation_vcd,
+            export_simulation_npz,
+            export_simulation_animation,
+            close_simulation,
+            set_simulation_cache_budget,
+            get_simulation_cache_stats,
             startup_frontend_ready,
             set_log_level,
             get_log_level,
@@ -77,29 +176,37 @@ fn main() {
             get_log_stats,
             log_message,
         ])
-        .register_uri_scheme_protocol("load-sim", |_, req| match handle_load_sim(req) {
-            Ok(bin_data) => Response::builder()
-                .status(StatusCode::OK)
-                .header("Access-Control-Allow-Origin", "*")
-                .header("Access-Control-Allow-Methods", "GET")
-                .header(
-                    header::CONTENT_TYPE,
-                    mime::APPLICATION_OCTET_STREAM.essence_str(),
-                )
-                .body(bin_data)
-                .unwrap(),
-            Err(error) => Response::builder()
-                .status(StatusCode::BAD_REQUEST)
-                .header("Access-Control-Allow-Origin", "*")
-                .header("Access-Control-Allow-Methods", "GET")
-                .header(header::CONTENT_TYPE, mime::TEXT_PLAIN.essence_str())
-                .body(error.as_bytes().to_vec())
-                .unwrap(),
+        // Frames are read off the main thread, as large files take a while
+        .register_asynchronous_uri_scheme_protocol("load-sim", |ctx, req, responder| {
+            let app = ctx.app_handle().clone();
+            spawn_blocking(move || responder.respond(load_sim_response(&app, req)));
         })
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
 
+fn load_sim_response(app: &AppHandle, req: Request<Vec<u8>>) -> Response<Vec<u8>> {
+    match handle_load_sim(app, req) {
+        Ok(bin_data) => Response::builder()
+            .status(StatusCode::OK)
+            .header("Access-Control-Allow-Origin", "*")
+            .header("Access-Control-Allow-Methods", "GET")
+            .header(
+                header::CONTENT_TYPE,
+                mime::APPLICATION_OCTET_STREAM.essence_str(),
+            )
+            .body(bin_data)
+            .unwrap(),
+        Err(error) => Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .header("Access-Control-Allow-Origin", "*")
+            .header("Access-Control-Allow-Methods", "GET")
+            .header(header::CONTENT_TYPE, mime::TEXT_PLAIN.essence_str())
+            .body(error.as_bytes().to_vec())
+            .unwrap(),
+    }
+}
+
 #[tauri::command]
 fn get_sim_version() -> String {
     qca_core::QCA_CORE_VERSION.to_string()
@@ -0,0 +1,548 @@
+//! Upgrades design (.qcd) and simulation (.qcs) files written by older
+//! versions.
+//!
+//! The `QCADesignFile` wrapper is versioned by `qca_forge_version`, designs
+//! and simulation metadata by `qca_core_version`. Migrations work on the
+//! JSON form of a file, so files that no longer deserialize can still be
+//! upgraded. Each migration applies to files older than its `before`
+//! version, in order; files without a version are treated as 0.0.0. Files
+//! from a newer version are refused.
+//!
+//! A migrated file replaces the original, which is kept next to it as
+//! `<file>.v<version>.bak`. Files that cannot be written are migrated in
+//! memory each time they are read.
+
+use crate::sim_format::{DESIGN_ENTRY, METADATA_ENTRY};
+use crate::sim_header::read_entry;
+use semver::Version;
+use serde_json::Value;
+use std::fs::File;
+use std::io::{BufReader, Write};
+use std::path::PathBuf;
+
+pub const QCA_FORGE_VERSION: &str = env!("CARGO_PKG_VERSION");
+const UNVERSIONED: &str = "0.0.0";
+
+pub struct Migration {
+    /// Files older than this version are migrated.
+    pub before: &'static str,
+    pub description: &'static str,
+    pub apply: fn(&mut Value) -> Result<(), String>,
+}
+
+/// Migrations of the `QCADesignFile` wrapper, by `qca_forge_version`.
+pub const DESIGN_FILE_MIGRATIONS: &[Migration] = &[];
+
+/// Migrations of a `QCADesign`, by `qca_core_version`.
+pub const DESIGN_MIGRATIONS: &[Migration] = &[];
+
+/// Migrations of `QCASimulationMetadata`, by `qca_core_version`.
+pub const METADATA_MIGRATIONS: &[Migration] = &[];
+
+/// The version stored under `key`. Files from before versions were stored
+/// have none.
+fn file_version<'a>(value: &'a Value, key: &str) -> &'a str {
+    value
+        .get(key)
+        .and_then(Value::as_str)
+        .unwrap_or(UNVERSIONED)
+}
+
+fn parse_version(version: &str) -> Result<Version, String> {
+    Version::parse(version.trim_start_matches('v'))
+        .map_err(|_err| format!("Invalid file version '{}'", version))
+}
+
+/// Runs the migrations that apply to `value`, whose version is stored under
+/// `key`, and returns their descriptions. The version is updated to
+/// `current` if any migration ran.
+fn migrate(
+    value: &mut Value,
+    key: &str,
+    current: &str,
+    migrations: &[Migration],
+    what: &str,
+) -> Result<Vec<&'static str>, String> {
+    let version = parse_version(file_version(value, key))?;
+    let current_version = parse_version(current)?;
+
+    // Pre-releases of the current version are not newer
+    let release = |version: &Version| (version.major, version.minor, version.patch);
+    if release(&version) > release(&current_version) {
+        return Err(format!(
+            "{} is from version {}, which is newer than this version ({}). Update QCA Forge to open it.",
+            what, version, current
+        ));
+    }
+
+    let mut applied = vec![];
+    for migration in migrations {
+        if version < parse_version(migration.before)? {
+            (migration.apply)(value)
+                .map_err(|err| format!("{} migration failed: {}", what, err))?;
+            applied.push(migration.description);
+        }
+    }
+    if !applied.is_empty() {
+        value[key] = Value::String(current.to_string());
+    }
+    Ok(applied)
+}
+
+pub fn migrate_design(design: &mut Value) -> Result<Vec<&'static str>, String> {
+    migrate(
+        design,
+        "qca_core_version",
+        qca_core::QCA_CORE_VERSION,
+        DESIGN_MIGRATIONS,
+        "Design",
+    )
+}
+
+pub fn migrate_metadata(metadata: &mut Value) -> Result<Vec<&'static str>, String> {
+    migrate(
+        metadata,
+        "qca_core_version",
+        qca_core::QCA_CORE_VERSION,
+        METADATA_MIGRATIONS,
+        "Simulation",
+    )
+}
+
+/// Migrates a `QCADesignFile` and the design it holds.
+pub fn migrate_design_file(design_file: &mut Value) -> Result<Vec<&'static str>, String> {
+    let mut applied = migrate(
+        design_file,
+        "qca_forge_version",
+        QCA_FORGE_VERSION,
+        DESIGN_FILE_MIGRATIONS,
+        "Design file",
+    )?;
+    let design = design_file
+        .get_mut("design")
+        .ok_or("Design file has no design")?;
+    applied.extend(migrate_design(design)?);
+    Ok(applied)
+}
+
+/// Copies a file to `<file>.v<version>.bak`. An existing backup is kept,
+/// as it already holds the file as it was at that version.
+fn backup_file(filename: &str, version: &str) -> Result<PathBuf, String> {
+    let backup = PathBuf::from(format!("{}.v{}.bak", filename, version));
+    if !backup.exists() {
+        std::fs::copy(filename, &backup).map_err(|_err| "Failed to back up file")?;
+    }
+    Ok(backup)
+}
+
+/// Writes a file through a temporary file, so an existing file is only
+/// replaced once the new contents are complete.
+pub fn replace_file(
+    filename: &str,
+    write: impl FnOnce(File) -> Result<(), String>,
+) -> Result<(), String> {
+    let temporary = format!("{}.partial", filename);
+    let file = File::create(&temporary).map_err(|_err| "Failed to create file")?;
+    if let Err(err) = write(file) {
+        let _ = std::fs::remove_file(&temporary);
+        return Err(err);
+    }
+    std::fs::rename(&temporary, filename).map_err(|_err| "Failed to replace file".into())
+}
+
+/// Replaces a migrated file, keeping the original as a backup. Files that
+/// cannot be written, e.g. in read-only locations, are left as they are.
+fn save_migrated(
+    filename: &str,
+    version: &str,
+    applied: &[&str],
+    write: impl FnOnce(File) -> Result<(), String>,
+) {
+    match backup_file(filename, version).and_then(|backup| {
+        replace_file(filename, write)?;
+        Ok(backup)
+    }) {
+        Ok(backup) => log::info!(
+            "Migrated {} from version {} ({}), original kept as {}",
+            filename,
+            version,
+            applied.join(", "),
+            backup.display()
+        ),
+        Err(err) => log::warn!(
+            "Migrated {} from version {} in memory only ({}): {}",
+            filename,
+            version,
+            applied.join(", "),
+            err
+        ),
+    }
+}
+
+/// Migrates the contents of a .qcd file. If migrations ran, the original is
+/// backed up and replaced by the migrated file, whose contents are returned.
+pub fn migrate_design_file_contents(filename: &str, contents: String) -> Result<String, String> {
+    migrate_file_contents(filename, contents, migrate_design_file)
+}
+
+fn migrate_file_contents(
+    filename: &str,
+    contents: String,
+    migrate: impl Fn(&mut Value) -> Result<Vec<&'static str>, String>,
+) -> Result<String, String> {
+    let mut design_file: Value =
+        serde_json::from_str(&contents).map_err(|_err| "Invalid design file")?;
+    let version = file_version(&design_file, "qca_forge_version").to_string();
+
+    let applied = migrate(&mut design_file)?;
+    if applied.is_empty() {
+        return Ok(contents);
+    }
+
+    let migrated = serde_json::to_string_pretty(&design_file)
+        .map_err(|_err| "Failed to serialize design file")?;
+    save_migrated(filename, &version, &applied, |mut file| {
+        file.write_all(migrated.as_bytes())
+            .map_err(|_err| "Failed to write to file".into())
+    });
+    Ok(migrated)
+}
+
+/// Migrates the design and metadata of a .qcs file in place, copying the
+/// sample data unchanged. Returns the migrations that ran. Readers of the
+/// design and metadata migrate them again in memory, so files that could
+/// not be rewritten still open.
+pub fn migrate_simulation_file(filename: &str) -> Result<Vec<&'static str>, String> {
+    migrate_archive(filename, |value, is_design| match is_design {
+        true => migrate_design(value),
+        false => migrate_metadata(value),
+    })
+}
+
+fn migrate_archive(
+    filename: &str,
+    migrate_entry: impl Fn(&mut Value, bool) -> Result<Vec<&'static str>, String>,
+) -> Result<Vec<&'static str>, String> {
+    let open_archive = || {
+        File::open(filename)
+            .map(|file| tar::Archive::new(BufReader::new(file)))
+            .map_err(|_err| "Failed to open file".to_string())
+    };
+
+    // Migrate the design and metadata entries, keyed by their position in
+    // the archive
+    let mut archive = open_archive()?;
+    let mut replaced = vec![];
+    let mut applied = vec![];
+    let mut version = None;
+    for (i, entry) in archive
+        .entries_with_seek()
+        .map_err(|_err| "Failed to read simulation file")?
+        .enumerate()
+    {
+        let mut entry = entry.map_err(|_err| "Failed to read simulation file")?;
+        let path = entry
+            .path()
+            .map(|path| path.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let is_design = path == DESIGN_ENTRY;
+        if !is_design && path != METADATA_ENTRY {
+            continue;
+        }
+        let mut value: Value = serde_json::from_slice(&read_entry(&mut entry)?)
+            .map_err(|_err| "Invalid simulation file")?;
+
+        let entry_version = file_version(&value, "qca_core_version").to_string();
+        let entry_applied = migrate_entry(&mut value, is_design)?;
+        if version.is_none() {
+            version = Some(entry_version);
+        }
+        if !entry_applied.is_empty() {
+            let contents =
+                serde_json::to_vec(&value).map_err(|_err| "Failed to serialize simulation file")?;
+            replaced.push((i, contents));
+            applied.extend(entry_applied);
+        }
+    }
+    if applied.is_empty() {
+        return Ok(applied);
+    }
+
+    let version = version.unwrap_or(UNVERSIONED.to_string());
+    let mut archive = open_archive()?;
+    save_migrated(filename, &version, &applied, |file| {
+        let write_err = |_err| "Failed to write to file".to_string();
+        let mut builder = tar::Builder::new(file);
+        for (i, entry) in archive
+            .entries()
+            .map_err(|_err| "Failed to read simulation file")?
+            .enumerate()
+        {
+            let mut entry = entry.map_err(|_err| "Failed to read simulation file")?;
+            let path = entry
+                .path()
+                .map_err(|_err| "Failed to read simulation file")?
+                .into_owned();
+            let mut header = entry.header().clone();
+            match replaced.iter().find(|(index, _)| *index == i) {
+                Some((_, contents)) => {
+                    header.set_size(contents.len() as u64);
+                    builder.append_data(&mut header, path, contents.as_slice())
+                }
+                None => builder.append_data(&mut header, path, &mut entry),
+            }
+            .map_err(write_err)?;
+        }
+        builder.into_inner().map_err(write_err)?;
+        Ok(())
+    });
+    Ok(applied)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use qca_core::design::file::QCADesign;
+    use qca_core::simulation::file::{write_to_file, QCASimulationData};
+    use serde_json::json;
+    use std::io::Cursor;
+
+    /// No migrations ship yet, so the framework is tested with this one.
+    const TEST_MIGRATIONS: &[Migration] = &[Migration {
+        before: "0.1.0",
+        description: "Mark file",
+        apply: |value| {
+            value["migrated"] = json!(true);
+            Ok(())
+        },
+    }];
+
+    fn migrate_test_design_file(design_file: &mut Value) -> Result<Vec<&'static str>, String> {
+        migrate(
+            design_file,
+            "qca_forge_version",
+            QCA_FORGE_VERSION,
+            TEST_MIGRATIONS,
+            "Design file",
+        )
+    }
+
+    fn design_file(version: Option<&str>) -> Value {
+        let mut design_file = json!({
+            "design": design(qca_core::QCA_CORE_VERSION),
+            "designer_properties": { "camera_zoom_enabled": false },
+        });
+        if let Some(version) = version {
+            design_file["qca_forge_version"] = json!(version);
+        }
+        design_file
+    }
+
+    fn design(version: &str) -> Value {
+        json!({
+            "qca_core_version": version,
+            "layers": [],
+            "cell_architectures": {},
+            "simulation_settings": {
+                "selected_simulation_model_id": null,
+                "simulation_model_settings": {},
+            },
+        })
+    }
+
+    fn temp_path(name: &str) -> String {
+        std::env::temp_dir()
+            .join(format!(
+                "qca-forge-migration-{}-{}",
+                std::process::id(),
+                name
+            ))
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn migrations_are_ordered_and_not_newer_than_their_files() {
+        let lists = [
+            (DESIGN_FILE_MIGRATIONS, QCA_FORGE_VERSION),
+            (DESIGN_MIGRATIONS, qca_core::QCA_CORE_VERSION),
+            (METADATA_MIGRATIONS, qca_core::QCA_CORE_VERSION),
+        ];
+        for (migrations, current) in lists {
+            let versions: Vec<Version> = migrations
+                .iter()
+                .map(|migration| parse_version(migration.before).unwrap())
+                .collect();
+            assert!(versions.windows(2).all(|pair| pair[0] <= pair[1]));
+            assert!(versions
+                .iter()
+                .all(|version| *version <= parse_version(current).unwrap()));
+        }
+    }
+
+    #[test]
+    fn migrates_older_files() {
+        let mut design_file = design_file(Some("0.0.1"));
+        let applied = migrate_test_design_file(&mut design_file).unwrap();
+
+        assert_eq!(applied, ["Mark file"]);
+        assert_eq!(design_file["migrated"], true);
+        assert_eq!(design_file["qca_forge_version"], QCA_FORGE_VERSION);
+        // Other contents are kept
+        assert_eq!(
+            design_file["designer_properties"]["camera_zoom_enabled"],
+            false
+        );
+    }
+
+    #[test]
+    fn current_files_are_not_migrated() {
+        let mut current = design_file(Some(QCA_FORGE_VERSION));
+        let original = current.clone();
+        assert!(migrate_design_file(&mut current).unwrap().is_empty());
+        assert_eq!(current, original);
+
+        // Pre-releases of the current version are not newer
+        let mut prerelease = design_file(Some(&format!("{}-beta.1", QCA_FORGE_VERSION)));
+        assert!(migrate_design_file(&mut prerelease).is_ok());
+    }
+
+    #[test]
+    fn files_without_a_version_are_migrated_from_0_0_0() {
+        let mut design_file = design_file(None);
+        let applied = migrate_test_design_file(&mut design_file).unwrap();
+        assert_eq!(applied, ["Mark file"]);
+        assert_eq!(design_file["qca_forge_version"], QCA_FORGE_VERSION);
+    }
+
+    #[test]
+    fn refuses_newer_files() {
+        let err = migrate_design_file(&mut design_file(Some("99.0.0"))).unwrap_err();
+        assert!(err.starts_with("Design file is from version 99.0.0"));
+
+        let mut design_file = design_file(Some(QCA_FORGE_VERSION));
+        design_file["design"] = design("99.0.0");
+        let err = migrate_design_file(&mut design_file).unwrap_err();
+        assert!(err.starts_with("Design is from version 99.0.0"));
+
+        let mut metadata = json!({ "qca_core_version": "99.0.0" });
+        assert!(migrate_metadata(&mut metadata).is_err());
+        assert!(migrate_design(&mut json!({ "qca_core_version": "invalid" })).is_err());
+    }
+
+    #[test]
+    fn migrates_in_memory_when_the_file_cannot_be_replaced() {
+        let filename = temp_path("read-only.qcd");
+        let contents = design_file(Some("0.0.1")).to_string();
+        std::fs::write(&filename, &contents).unwrap();
+        // The temporary file the migration writes through cannot be created
+        std::fs::create_dir(format!("{}.partial", filename)).unwrap();
+
+        let migrated = migrate_file_contents(&filename, contents.clone(), migrate_test_design_file);
+        let on_disk = std::fs::read_to_string(&filename).unwrap();
+        let _ = std::fs::remove_dir(format!("{}.partial", filename));
+        let _ = std::fs::remove_file(format!("{}.v0.0.1.bak", filename));
+        let _ = std::fs::remove_file(&filename);
+
+        let migrated: Value = serde_json::from_str(&migrated.unwrap()).unwrap();
+        assert_eq!(migrated["qca_forge_version"], QCA_FORGE_VERSION);
+        assert_eq!(on_disk, contents);
+    }
+
+    #[test]
+    fn rewrites_simulation_files_keeping_their_samples() {
+        let filename = temp_path("old.qcs");
+        let design: QCADesign = serde_json::from_value(design("0.0.1")).unwrap();
+        let metadata = serde_json::from_value(json!({
+            "qca_core_version": "0.0.1",
+            "start_time": "2000-01-01T00:00:00+00:00",
+            "duration": { "seconds": 1, "nanoseconds": 0 },
+            "num_samples": 3,
+            "stored_cells": [],
+        }))
+        .unwrap();
+        let data = QCASimulationData {
+            metadata,
+            clock_data: [
+                vec![0.0, 0.5, 1.0],
+                vec![1.0, 0.5, 0.0],
+                vec![0.25; 3],
+                vec![-0.25; 3],
+            ],
+            cells_data: vec![],
+        };
+        write_to_file(File::create(&filename).unwrap(), &design, &data).unwrap();
+        let original = std::fs::read(&filename).unwrap();
+
+        let applied = migrate_archive(&filename, |value, is_design| match is_design {
+            true => Ok(vec![]),
+            false => migrate(
+                value,
+                "qca_core_version",
+                qca_core::QCA_CORE_VERSION,
+                TEST_MIGRATIONS,
+                "Simulation",
+            ),
+        });
+        let migrated = std::fs::read(&filename).unwrap();
+        let backup = std::fs::read(format!("{}.v0.0.1.bak", filename));
+        let _ = std::fs::remove_file(format!("{}.v0.0.1.bak", filename));
+        let _ = std::fs::remove_file(&filename);
+
+        assert_eq!(applied.unwrap(), ["Mark file"]);
+        assert_eq!(backup.unwrap(), original);
+        let contents = |archive: &[u8]| -> Vec<(String, Vec<u8>)> {
+            tar::Archive::new(Cursor::new(archive))
+                .entries()
+                .unwrap()
+                .map(|entry| {
+                    let mut entry = entry.unwrap();
+                    let path = entry.path().unwrap().to_string_lossy().into_owned();
+                    (path, read_entry(&mut entry).unwrap())
+                })
+                .collect()
+        };
+        let (before, after) = (contents(&original), contents(&migrated));
+        assert_eq!(before.len(), after.len());
+        for ((path, before), (migrated_path, after)) in before.iter().zip(&after) {
+            assert_eq!(path, migrated_path);
+            if path == METADATA_ENTRY {
+                let metadata: Value = serde_json::from_slice(after).unwrap();
+                assert_eq!(metadata["migrated"], true);
+                assert_eq!(metadata["qca_core_version"], qca_core::QCA_CORE_VERSION);
+                assert_eq!(metadata["num_samples"], 3);
+            } else {
+                assert_eq!(before, after);
+            }
+        }
+    }
+
+    #[test]
+    fn leaves_current_simulation_files_alone() {
+        let filename = temp_path("current.qcs");
+        let design: QCADesign = serde_json::from_value(design(qca_core::QCA_CORE_VERSION)).unwrap();
+        let metadata = serde_json::from_value(json!({
+            "qca_core_version": qca_core::QCA_CORE_VERSION,
+            "start_time": "2000-01-01T00:00:00+00:00",
+            "duration": { "seconds": 0, "nanoseconds": 0 },
+            "num_samples": 0,
+            "stored_cells": [],
+        }))
+        .unwrap();
+        let data = QCASimulationData {
+            metadata,
+            clock_data: Default::default(),
+            cells_data: vec![],
+        };
+        write_to_file(File::create(&filename).unwrap(), &design, &data).unwrap();
+        let original = std::fs::read(&filename).unwrap();
+
+        let applied = migrate_simulation_file(&filename);
+        let after = std::fs::read(&filename).unwrap();
+        let _ = std::fs::remove_file(&filename);
+
+        assert!(applied.unwrap().is_empty());
+        assert_eq!(after, original);
+    }
+}
@@ -0,0 +1,386 @@
+use crate::signal::{
+    cell_name, polarization_count, SignalIndex, SignalKind, SignalSource, SignalView, CLOCK_COUNT,
+};
+use crate::sim_cache::SimulationCache;
+use serde_json::json;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use tauri::State;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+const NPY_MAGIC: &[u8] = b"\x93NUMPY\x01\x00";
+const NPY_HEADER_ALIGNMENT: usize = 64;
+
+type NpzWriter = ZipWriter<BufWriter<File>>;
+
+fn npz_err<E: std::fmt::Display>(err: E) -> String {
+    format!("Failed to write archive: {}", err)
+}
+
+/// Starts an `.npy` entry holding a C-ordered array of the given dtype and
+/// shape. The caller writes the array data right after.
+fn start_npy(
+    writer: &mut NpzWriter,
+    name: &str,
+    descr: &str,
+    shape: &[usize],
+) -> Result<(), String> {
+    let options = SimpleFileOptions::default()
+        .compression_method(CompressionMethod::Stored)
+        .large_file(true);
+    writer
+        .start_file(format!("{}.npy", name), options)
+        .map_err(npz_err)?;
+
+    let shape = match shape.len() {
+        1 => format!("({},)", shape[0]),
+        _ => format!(
+            "({})",
+            shape
+                .iter()
+                .map(|dim| dim.to_string())
+                .collect::<Vec<String>>()
+                .join(", ")
+        ),
+    };
+    let mut header = format!(
+        "{{'descr': '{}', 'fortran_order': False, 'shape': {}, }}",
+        descr, shape
+    );
+    let unpadded = NPY_MAGIC.len() + 2 + header.len() + 1;
+    let padded =
+        (unpadded + NPY_HEADER_ALIGNMENT - 1) / NPY_HEADER_ALIGNMENT * NPY_HEADER_ALIGNMENT;
+    header.push_str(&" ".repeat(padded - unpadded));
+    header.push('\n');
+
+    writer.write_all(NPY_MAGIC).map_err(npz_err)?;
+    writer
+        .write_all(&(header.len() as u16).to_le_bytes())
+        .map_err(npz_err)?;
+    writer.write_all(header.as_bytes()).map_err(npz_err)?;
+    Ok(())
+}
+
+fn write_f64s(writer: &mut NpzWriter, values: impl Iterator<Item = f64>) -> Result<(), String> {
+    for value in values {
+        writer.write_all(&value.to_le_bytes()).map_err(npz_err)?;
+    }
+    Ok(())
+}
+
+/// Writes exactly `num_samples` samples of a signal, so the data matches
+/// the declared shape. Signals stored shorter than the run are NaN padded.
+fn write_samples(
+    writer: &mut NpzWriter,
+    view: SignalView,
+    num_samples: usize,
+) -> Result<(), String> {
+    write_f64s(
+        writer,
+        view.iter()
+            .chain(std::iter::repeat(f64::NAN))
+            .take(num_samples),
+    )
+}
+
+fn write_i64s(writer: &mut NpzWriter, values: impl Iterator<Item = i64>) -> Result<(), String> {
+    for value in values {
+        writer.write_all(&value.to_le_bytes()).map_err(npz_err)?;
+    }
+    Ok(())
+}
+
+/// Writes strings as a NumPy unicode array, which stores UTF-32 code points
+/// padded to the longest string.
+fn write_unicode(
+    writer: &mut NpzWriter,
+    name: &str,
+    values: &[String],
+    shape: &[usize],
+) -> Result<(), String> {
+    let width = values
+        .iter()
+        .map(|value| value.chars().count())
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    start_npy(writer, name, &format!("<U{}", width), shape)?;
+    for value in values {
+        let mut chars: Vec<u32> = value.chars().map(|c| c as u32).collect();
+        chars.resize(width, 0);
+        for c in chars {
+            writer.write_all(&c.to_le_bytes()).map_err(npz_err)?;
+        }
+    }
+    Ok(())
+}
+
+/// Exports a simulation as a NumPy `.npz` archive with the arrays
+/// `clock` (4×N), `cells` (C×P×N, NaN padded for cells with fewer
+/// polarizations), `cell_indices` (C×2), `labels` (C), `positions` (C×2),
+/// `layer_ids` (C) and a JSON `metadata` string. Signals stored with fewer
+/// than N samples are NaN padded as well.
+#[tauri::command(async)]
+pub fn export_simulation_npz(
+    cache: State<'_, SimulationCache>,
+    filename: String,
+    output_filename: String,
+) -> Result<(), String> {
+    let access = cache.access(&filename)?;
+    write_npz(access.source(), &output_filename)
+}
+
+fn write_npz(simulation: &dyn SignalSource, output_filename: &str) -> Result<(), String> {
+    let (design, metadata) = (simulation.design(), simulation.metadata());
+    let num_samples = metadata.num_samples;
+    let stored_cells = &metadata.stored_cells;
+
+    let polarization_counts = stored_cells
+        .iter()
+        .map(|cell| polarization_count(design, cell))
+        .collect::<Result<Vec<usize>, String>>()?;
+    let max_polarizations = polarization_counts.iter().copied().max().unwrap_or(1);
+
+    let file = File::create(output_filename).map_err(|_err| "Failed to create file")?;
+    let mut writer = ZipWriter::new(BufWriter::new(file));
+
+    start_npy(&mut writer, "clock", "<f8", &[CLOCK_COUNT, num_samples])?;
+    for clock in 0..CLOCK_COUNT {
+        let index = SignalIndex {
+            kind: SignalKind::Clock,
+            index: clock,
+            subindex: None,
+        };
+        write_samples(&mut writer, simulation.signal_view(&index)?, num_samples)?;
+    }
+
+    start_npy(
+        &mut writer,
+        "cells",
+        "<f8",
+        &[stored_cells.len(), max_polarizations, num_samples],
+    )?;
+    for (i, polarization_n) in polarization_counts.iter().enumerate() {
+        for polarization in 0..max_polarizations {
+            if polarization < *polarization_n {
+                let index = SignalIndex {
+                    kind: SignalKind::Cell,
+                    index: i,
+                    subindex: Some(polarization),
+                };
+                write_samples(&mut writer, simulation.signal_view(&index)?, num_samples)?;
+            } else {
+                write_f64s(&mut writer, std::iter::repeat(f64::NAN).take(num_samples))?;
+            }
+        }
+    }
+
+    start_npy(&mut writer, "cell_indices", "<i8", &[stored_cells.len(), 2])?;
+    write_i64s(
+        &mut writer,
+        stored_cells
+            .iter()
+            .flat_map(|cell| [cell.layer as i64, cell.cell as i64]),
+    )?;
+
+    let labels: Vec<String> = stored_cells
+        .iter()
+        .map(|cell| cell_name(design, cell))
+        .collect();
+    write_unicode(&mut writer, "labels", &labels, &[labels.len()])?;
+
+    start_npy(&mut writer, "positions", "<f8", &[stored_cells.len(), 2])?;
+    for cell in stored_cells {
+        let qca_cell = design
+            .layers
+            .get(cell.layer)
+            .and_then(|layer| layer.cells.get(cell.cell))
+            .ok_or(format!("Cell {}-{} does not exist", cell.layer, cell.cell))?;
+        write_f64s(
+            &mut writer,
+            [qca_cell.position[0], qca_cell.position[1]].into_iter(),
+        )?;
+    }
+
+    start_npy(&mut writer, "layer_ids", "<i8", &[stored_cells.len()])?;
+    write_i64s(
+        &mut writer,
+        stored_cells.iter().map(|cell| cell.layer as i64),
+    )?;
+
+    let model_settings = design
+        .simulation_settings
+        .selected_simulation_model_id
+        .as_ref()
+        .and_then(|id| design.simulation_settings.simulation_model_settings.get(id));
+    let metadata = json!({
+        "design": design,
+        "model_id": design.simulation_settings.selected_simulation_model_id,
+        "model_settings": model_settings,
+        "simulation": metadata,
+    });
+    write_unicode(&mut writer, "metadata", &[metadata.to_string()], &[])?;
+
+    writer.finish().map_err(npz_err)?.flush().map_err(npz_err)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim_cache::LoadedSimulation;
+    use qca_core::simulation::file::{QCACellData, QCASimulationData};
+    use std::io::Read;
+    use zip::ZipArchive;
+
+    /// The dtype, shape and data of an `.npy` entry. Only the header
+    /// written by `start_npy` is understood.
+    fn read_npy(archive: &mut ZipArchive<File>, name: &str) -> (String, Vec<usize>, Vec<u8>) {
+        let mut bytes = vec![];
+        archive
+            .by_name(&format!("{}.npy", name))
+            .unwrap()
+            .read_to_end(&mut bytes)
+            .unwrap();
+        assert_eq!(&bytes[..NPY_MAGIC.len()], NPY_MAGIC);
+
+        let start = NPY_MAGIC.len() + 2;
+        let header_len = u16::from_le_bytes([bytes[start - 2], bytes[start - 1]]) as usize;
+        assert_eq!((start + header_len) % NPY_HEADER_ALIGNMENT, 0);
+        let header = std::str::from_utf8(&bytes[start..start + header_len]).unwrap();
+        let field = |key: &str| {
+            let from = header.find(&format!("'{}': ", key)).unwrap() + key.len() + 4;
+            &header[from..]
+        };
+        let descr = field("descr")[1..].split('\'').next().unwrap().to_string();
+        let shape = field("shape")[1..]
+            .split(')')
+            .next()
+            .unwrap()
+            .split(',')
+            .map(str::trim)
+            .filter(|dim| !dim.is_empty())
+            .map(|dim| dim.parse().unwrap())
+            .collect();
+        (descr, shape, bytes[start + header_len..].to_vec())
+    }
+
+    fn f64s(data: &[u8]) -> Vec<f64> {
+        data.chunks(8)
+            .map(|value| f64::from_le_bytes(value.try_into().unwrap()))
+            .collect()
+    }
+
+    fn simulation() -> LoadedSimulation {
+        let cell = serde_json::json!({
+            "position": [2.0, 4.0],
+            "rotation": 0.0,
+            "typ": "Normal",
+            "clock_phase_shift": 0.0,
+            "dot_probability_distribution": [],
+            "label": "A",
+        });
+        let architecture = |dot_count: u8| {
+            serde_json::json!({
+                "side_length": 18.0,
+                "dot_diameter": 5.0,
+                "dot_count": dot_count,
+                "dot_positions": [],
+                "dot_tunnels": [],
+            })
+        };
+        let layer = |architecture: &str| {
+            serde_json::json!({
+                "name": architecture,
+                "visible": true,
+                "cell_architecture_id": architecture,
+                "cells": [cell.clone()],
+                "z_position": 0.0,
+            })
+        };
+        let design = serde_json::from_value(serde_json::json!({
+            "qca_core_version": qca_core::QCA_CORE_VERSION,
+            "layers": [layer("binary"), layer("ternary")],
+            "cell_architectures": {
+                "binary": architecture(4),
+                "ternary": architecture(8),
+            },
+            "simulation_settings": {
+                "selected_simulation_model_id": null,
+                "simulation_model_settings": {},
+            },
+        }))
+        .unwrap();
+        let metadata = serde_json::from_value(serde_json::json!({
+            "qca_core_version": qca_core::QCA_CORE_VERSION,
+            "start_time": "2024-05-01T12:00:00+00:00",
+            "duration": { "seconds": 1, "nanoseconds": 0 },
+            "num_samples": 4,
+            "stored_cells": [{ "layer": 0, "cell": 0 }, { "layer": 1, "cell": 0 }],
+        }))
+        .unwrap();
+        LoadedSimulation {
+            design,
+            data: QCASimulationData {
+                metadata,
+                clock_data: [
+                    vec![0.0, 1.0, 2.0, 3.0],
+                    vec![0.0; 4],
+                    vec![0.0; 4],
+                    // Shorter than the run
+                    vec![1.0, 1.0],
+                ],
+                cells_data: vec![
+                    // Shorter than the run
+                    QCACellData {
+                        data: vec![0.5, -0.5, 0.25],
+                    },
+                    // Two interleaved polarizations
+                    QCACellData {
+                        data: vec![1.0, -1.0, 1.0, -1.0, 1.0, -1.0, 1.0, -1.0],
+                    },
+                ],
+            },
+        }
+    }
+
+    #[test]
+    fn arrays_match_their_declared_shape() {
+        let path = std::env::temp_dir().join(format!("qca-forge-npz-{}.npz", std::process::id()));
+        let written = write_npz(&simulation(), path.to_str().unwrap());
+        let file = File::open(&path);
+        let _ = std::fs::remove_file(&path);
+        written.unwrap();
+        let mut archive = ZipArchive::new(file.unwrap()).unwrap();
+
+        let (descr, shape, data) = read_npy(&mut archive, "clock");
+        assert_eq!((descr.as_str(), shape.as_slice()), ("<f8", &[4, 4][..]));
+        let clock = f64s(&data);
+        assert_eq!(clock[..4], [0.0, 1.0, 2.0, 3.0]);
+        assert_eq!(clock[12..14], [1.0, 1.0]);
+        assert!(clock[14..].iter().all(|value| value.is_nan()));
+
+        let (descr, shape, data) = read_npy(&mut archive, "cells");
+        assert_eq!((descr.as_str(), shape.as_slice()), ("<f8", &[2, 2, 4][..]));
+        let cells = f64s(&data);
+        assert_eq!(cells.len(), 16);
+        assert_eq!(cells[..3], [0.5, -0.5, 0.25]);
+        // Padded at the end of the short cell and for its missing
+        // polarization
+        assert!(cells[3..8].iter().all(|value| value.is_nan()));
+        assert_eq!(cells[8..12], [1.0; 4]);
+        assert_eq!(cells[12..], [-1.0; 4]);
+
+        let (descr, shape, data) = read_npy(&mut archive, "cell_indices");
+        assert_eq!((descr.as_str(), shape.as_slice()), ("<i8", &[2, 2][..]));
+        assert_eq!(data.len(), 4 * 8);
+
+        let (descr, shape, data) = read_npy(&mut archive, "labels");
+        assert_eq!((descr.as_str(), shape.as_slice()), ("<U1", &[2][..]));
+        assert_eq!(data.len(), 2 * 4);
+
+        let (_, shape, _) = read_npy(&mut archive, "metadata");
+        assert!(shape.is_empty());
+    }
+}
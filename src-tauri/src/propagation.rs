@@ -0,0 +1,221 @@
+use crate::clocking::{cell_clock_zone, clock_holds};
+use crate::digital::{digitize, DigitizeOptions, LogicValue};
+use crate::signal::{stored_cell_index, SignalIndex, SignalKind, SignalView};
+use crate::sim_cache::SimulationCache;
+use crate::statistics::Transition;
+use crate::timing::simulation_timing;
+use qca_core::objects::cell::QCACellIndex;
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+use tauri::State;
+
+/// A cell polarization, `polarization` defaulting to the first one.
+#[derive(Clone, Copy, Deserialize)]
+pub struct CellPolarization {
+    cell: QCACellIndex,
+    polarization: Option<usize>,
+}
+
+#[derive(Serialize)]
+pub struct PropagationEvent {
+    input_sample: usize,
+    input_transition: Transition,
+    /// The first output transition in the same clock cycle, if the output
+    /// switched in it.
+    output_sample: Option<usize>,
+    output_transition: Option<Transition>,
+    delay_samples: Option<usize>,
+    delay_time: Option<f64>,
+}
+
+#[derive(Serialize)]
+pub struct PropagationDelay {
+    events: Vec<PropagationEvent>,
+    mean_delay_samples: Option<f64>,
+    max_delay_samples: Option<usize>,
+    mean_delay_time: Option<f64>,
+    max_delay_time: Option<f64>,
+}
+
+/// Samples at which the digitised signal switches between logic levels,
+/// ignoring the unknown stretches in between.
+fn transitions(view: &SignalView, options: &DigitizeOptions) -> Vec<(usize, Transition)> {
+    let mut transitions = vec![];
+    let mut value = LogicValue::Unknown;
+    let mut level = LogicValue::Unknown;
+    for (i, polarization) in view.iter().enumerate() {
+        value = digitize(polarization, value, options);
+        if value == LogicValue::Unknown || value == level {
+            continue;
+        }
+        if level != LogicValue::Unknown {
+            transitions.push((
+                i,
+                match value {
+                    LogicValue::One => Transition::Rise,
+                    _ => Transition::Fall,
+                },
+            ));
+        }
+        level = value;
+    }
+    transitions
+}
+
+/// Pairs every input transition with the first output transition in the
+/// same clock cycle, which runs from the input transition until the input
+/// can switch again at the start of the hold phase after the next one. An
+/// output transition is paired at most once. Returns the paired output
+/// transition of every input transition.
+fn pair_transitions(
+    inputs: &[(usize, Transition)],
+    outputs: &[(usize, Transition)],
+    input_holds: &[Range<usize>],
+) -> Vec<Option<(usize, Transition)>> {
+    let mut remaining = outputs.iter().peekable();
+    let mut pairs = vec![];
+    for (input_sample, _) in inputs {
+        let cycle_end = input_holds
+            .iter()
+            .position(|hold| hold.end > *input_sample)
+            .and_then(|hold| input_holds.get(hold + 1))
+            .map_or(usize::MAX, |next| next.start);
+
+        while remaining
+            .peek()
+            .map_or(false, |(sample, _)| sample < input_sample)
+        {
+            remaining.next();
+        }
+        let matched = match remaining.peek() {
+            Some((sample, _)) if *sample < cycle_end => remaining.next().copied(),
+            _ => None,
+        };
+        pairs.push(matched);
+    }
+    pairs
+}
+
+/// Measures the propagation delay from an input to an output cell. Every
+/// input transition is paired with the first output transition in the same
+/// clock cycle of the input, so outputs up to a clock cycle behind the
+/// input are measured. Input transitions the output does not follow within
+/// the cycle are reported without a delay.
+#[tauri::command(async)]
+pub fn measure_propagation_delay(
+    cache: State<'_, SimulationCache>,
+    filename: String,
+    input: CellPolarization,
+    output: CellPolarization,
+    options: DigitizeOptions,
+    clock_threshold: f64,
+) -> Result<PropagationDelay, String> {
+    let access = cache.access(&filename)?;
+    let simulation = access.source();
+    let (design, metadata) = (simulation.design(), simulation.metadata());
+    // Delays are only given in samples without the clock timing
+    let sample_period = simulation_timing(design, metadata.num_samples)
+        .map(|timing| timing.sample_period())
+        .ok();
+
+    let view = |signal: &CellPolarization| {
+        let index = SignalIndex {
+            kind: SignalKind::Cell,
+            index: stored_cell_index(metadata, &signal.cell)?,
+            subindex: signal.polarization,
+        };
+        simulation.signal_view(&index)
+    };
+    let input_transitions = transitions(&view(&input)?, &options);
+    let output_transitions = transitions(&view(&output)?, &options);
+    let holds = clock_holds(simulation, clock_threshold)?;
+    let input_holds = &holds[cell_clock_zone(design, &input.cell)?];
+
+    let events: Vec<PropagationEvent> =
+        pair_transitions(&input_transitions, &output_transitions, input_holds)
+            .into_iter()
+            .zip(input_transitions.iter().copied())
+            .map(|(matched, (input_sample, input_transition))| {
+                let delay_samples = matched.map(|(sample, _)| sample - input_sample);
+                PropagationEvent {
+                    input_sample,
+                    input_transition,
+                    output_sample: matched.map(|(sample, _)| sample),
+                    output_transition: matched.map(|(_, transition)| transition),
+                    delay_samples,
+                    delay_time: delay_samples
+                        .zip(sample_period)
+                        .map(|(delay, period)| delay as f64 * period),
+                }
+            })
+            .collect();
+
+    let delays: Vec<usize> = events
+        .iter()
+        .filter_map(|event| event.delay_samples)
+        .collect();
+    let mean_delay_samples = match delays.len() {
+        0 => None,
+        n => Some(delays.iter().sum::<usize>() as f64 / n as f64),
+    };
+    let max_delay_samples = delays.iter().copied().max();
+
+    Ok(PropagationDelay {
+        events,
+        mean_delay_samples,
+        max_delay_samples,
+        mean_delay_time: mean_delay_samples
+            .zip(sample_period)
+            .map(|(delay, period)| delay * period),
+        max_delay_time: max_delay_samples
+            .zip(sample_period)
+            .map(|(delay, period)| delay as f64 * period),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Holds of the input's clock zone every 100 samples
+    const HOLDS: [Range<usize>; 4] = [20..40, 120..140, 220..240, 320..340];
+
+    #[test]
+    fn pairs_outputs_in_the_same_cycle() {
+        let inputs = [(15, Transition::Rise), (115, Transition::Fall)];
+        let outputs = [(80, Transition::Fall), (190, Transition::Rise)];
+        let pairs = pair_transitions(&inputs, &outputs, &HOLDS);
+        assert_eq!(
+            pairs,
+            [Some((80, Transition::Fall)), Some((190, Transition::Rise))]
+        );
+    }
+
+    #[test]
+    fn skips_inputs_the_output_does_not_follow() {
+        // The output misses the first input transition and only switches
+        // after the second one
+        let inputs = [(15, Transition::Rise), (215, Transition::Fall)];
+        let outputs = [(260, Transition::Rise)];
+        let pairs = pair_transitions(&inputs, &outputs, &HOLDS);
+        assert_eq!(pairs, [None, Some((260, Transition::Rise))]);
+    }
+
+    #[test]
+    fn pairs_each_output_once() {
+        // A glitching input switches twice in one cycle
+        let inputs = [(10, Transition::Rise), (18, Transition::Fall)];
+        let outputs = [(60, Transition::Fall), (70, Transition::Rise)];
+        let pairs = pair_transitions(&inputs, &outputs, &HOLDS);
+        assert_eq!(pairs[0], Some((60, Transition::Fall)));
+        assert_eq!(pairs[1], Some((70, Transition::Rise)));
+
+        // Transitions after the last full cycle pair until the end of the run
+        let pairs = pair_transitions(
+            &[(315, Transition::Rise)],
+            &[(390, Transition::Fall)],
+            &HOLDS,
+        );
+        assert_eq!(pairs[0], Some((390, Transition::Fall)));
+    }
+}
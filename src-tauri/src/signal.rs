@@ -0,0 +1,406 @@
+use qca_core::design::file::QCADesign;
+use qca_core::objects::cell::QCACellIndex;
+use qca_core::simulation::file::{QCASimulationData, QCASimulationMetadata};
+use serde::{Deserialize, Serialize};
+use serde_repr::{Deserialize_repr, Serialize_repr};
+
+pub const CLOCK_COUNT: usize = 4;
+const POLARIZATION_SUFFIXES: &str = "ABCDE";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize_repr, Deserialize_repr)]
+#[repr(u8)]
+pub enum SignalKind {
+    Clock = 0,
+    Cell = 1,
+    /// A signal evaluated from an expression, indexed by its position in
+    /// the requested derived signals.
+    Derived = 2,
+}
+
+/// Mirrors the frontend `SignalIndex`: clocks are indexed by clock number,
+/// cells by their position in `stored_cells` plus a polarization subindex.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct SignalIndex {
+    #[serde(rename = "type")]
+    pub kind: SignalKind,
+    pub index: usize,
+    pub subindex: Option<usize>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct SignalDescriptor {
+    pub index: SignalIndex,
+    pub name: String,
+    pub cell: Option<QCACellIndex>,
+}
+
+impl SignalDescriptor {
+    pub fn polarization_index(&self) -> usize {
+        self.index.subindex.unwrap_or(0)
+    }
+}
+
+pub fn polarization_count(design: &QCADesign, cell: &QCACellIndex) -> Result<usize, String> {
+    let layer = design
+        .layers
+        .get(cell.layer)
+        .ok_or(format!("Layer {} does not exist", cell.layer))?;
+    let architecture = design
+        .cell_architectures
+        .get(layer.cell_architecture_id.as_str())
+        .ok_or(format!(
+            "Cell architecture '{}' does not exist",
+            layer.cell_architecture_id
+        ))?;
+    Ok(architecture.dot_count as usize / 4)
+}
+
+/// The polarization each dot contributes to and the sign of its
+/// contribution, matching the frontend's `getPolarization`.
+pub fn dot_signs(dot_count: usize) -> Result<Vec<(usize, f64)>, String> {
+    match dot_count {
+        4 => Ok(vec![(0, 1.0), (0, -1.0), (0, 1.0), (0, -1.0)]),
+        8 => Ok(vec![
+            (1, -1.0),
+            (0, 1.0),
+            (1, 1.0),
+            (0, -1.0),
+            (1, -1.0),
+            (0, 1.0),
+            (1, 1.0),
+            (0, -1.0),
+        ]),
+        n => Err(format!(
+            "Cell architectures with {} dots are not supported",
+            n
+        )),
+    }
+}
+
+pub fn cell_name(design: &QCADesign, cell: &QCACellIndex) -> String {
+    design
+        .layers
+        .get(cell.layer)
+        .and_then(|layer| layer.cells.get(cell.cell))
+        .and_then(|cell| cell.label.clone())
+        .unwrap_or(format!("Cell {}-{}", cell.layer, cell.cell))
+}
+
+pub fn clock_name(clock: usize) -> String {
+    format!("Clock {}", clock)
+}
+
+/// Lists the signals of a simulation in the same order and with the same
+/// naming as the analyzer's signal list.
+pub fn list_signals(
+    design: &QCADesign,
+    metadata: &QCASimulationMetadata,
+) -> Result<Vec<SignalDescriptor>, String> {
+    let mut signals: Vec<SignalDescriptor> = (0..CLOCK_COUNT)
+        .map(|i| SignalDescriptor {
+            index: SignalIndex {
+                kind: SignalKind::Clock,
+                index: i,
+                subindex: None,
+            },
+            name: clock_name(i),
+            cell: None,
+        })
+        .collect();
+
+    for (i, cell) in metadata.stored_cells.iter().enumerate() {
+        signals.extend(cell_signals(design, i, cell)?);
+    }
+
+    Ok(signals)
+}
+
+/// Position of a cell in `stored_cells`, which indexes its cell data.
+pub fn stored_cell_index(
+    metadata: &QCASimulationMetadata,
+    cell: &QCACellIndex,
+) -> Result<usize, String> {
+    metadata
+        .stored_cells
+        .iter()
+        .position(|stored| stored == cell)
+        .ok_or(format!(
+            "Cell {}-{} is not stored in the simulation",
+            cell.layer, cell.cell
+        ))
+}
+
+pub fn cell_signals(
+    design: &QCADesign,
+    stored_index: usize,
+    cell: &QCACellIndex,
+) -> Result<Vec<SignalDescriptor>, String> {
+    let polarization_n = polarization_count(design, cell)?;
+    let name = cell_name(design, cell);
+
+    Ok((0..polarization_n)
+        .map(|j| SignalDescriptor {
+            index: SignalIndex {
+                kind: SignalKind::Cell,
+                index: stored_index,
+                subindex: Some(j),
+            },
+            name: match polarization_n {
+                1 => name.clone(),
+                _ => format!("{} {}", name, &POLARIZATION_SUFFIXES[j..j + 1]),
+            },
+            cell: Some(*cell),
+        })
+        .collect())
+}
+
+#[derive(Clone, Copy)]
+enum ViewSamples<'a> {
+    Values(&'a [f64]),
+    /// Little-endian `f64` samples as stored in a file.
+    Bytes(&'a [u8]),
+}
+
+/// A borrowed view of a single signal. Cell data is stored interleaved per
+/// polarization, so a view steps over the other polarizations.
+#[derive(Clone, Copy)]
+pub struct SignalView<'a> {
+    samples: ViewSamples<'a>,
+    step: usize,
+    offset: usize,
+}
+
+impl<'a> SignalView<'a> {
+    /// A view over samples that are not part of the simulation data, such as
+    /// evaluated derived signals.
+    pub fn from_samples(samples: &'a [f64]) -> SignalView<'a> {
+        SignalView {
+            samples: ViewSamples::Values(samples),
+            step: 1,
+            offset: 0,
+        }
+    }
+
+    /// A view over little-endian `f64` samples, `step` values per sample.
+    pub fn from_bytes(bytes: &'a [u8], step: usize, offset: usize) -> SignalView<'a> {
+        SignalView {
+            samples: ViewSamples::Bytes(bytes),
+            step,
+            offset,
+        }
+    }
+
+    fn raw_len(&self) -> usize {
+        match self.samples {
+            ViewSamples::Values(values) => values.len(),
+            ViewSamples::Bytes(bytes) => bytes.len() / 8,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        let values = self.raw_len().saturating_sub(self.offset);
+        (values + self.step - 1) / self.step
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, sample: usize) -> Option<f64> {
+        let i = sample * self.step + self.offset;
+        match self.samples {
+            ViewSamples::Values(values) => values.get(i).copied(),
+            ViewSamples::Bytes(bytes) => bytes
+                .get(i * 8..i * 8 + 8)
+                .map(|value| f64::from_le_bytes(value.try_into().unwrap())),
+        }
+    }
+
+    /// The samples of `window`, without copying them.
+    pub fn window(&self, window: &SampleWindow) -> SignalView<'a> {
+        let end = window.end * self.step;
+        let samples = match self.samples {
+            ViewSamples::Values(values) => ViewSamples::Values(&values[..end.min(values.len())]),
+            ViewSamples::Bytes(bytes) => ViewSamples::Bytes(&bytes[..(end * 8).min(bytes.len())]),
+        };
+        SignalView {
+            samples,
+            step: self.step * window.stride,
+            offset: self.offset + window.start * self.step,
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = f64> + Clone + 'a {
+        let view = *self;
+        (0..view.len()).filter_map(move |sample| view.get(sample))
+    }
+
+    pub fn to_vec(self) -> Vec<f64> {
+        self.iter().collect()
+    }
+}
+
+/// Read access to the signals of a simulation, whether parsed into memory
+/// or mapped from its file.
+pub trait SignalSource {
+    fn design(&self) -> &QCADesign;
+    fn metadata(&self) -> &QCASimulationMetadata;
+    fn signal_view(&self, index: &SignalIndex) -> Result<SignalView<'_>, String>;
+}
+
+pub fn signal_view<'a>(
+    design: &QCADesign,
+    data: &'a QCASimulationData,
+    index: &SignalIndex,
+) -> Result<SignalView<'a>, String> {
+    match index.kind {
+        SignalKind::Clock => data
+            .clock_data
+            .get(index.index)
+            .map(|clock| SignalView::from_samples(clock))
+            .ok_or(format!("Clock {} does not exist", index.index)),
+        SignalKind::Cell => {
+            let cell = data
+                .metadata
+                .stored_cells
+                .get(index.index)
+                .ok_or(format!("Stored cell {} does not exist", index.index))?;
+            let polarization_n = polarization_count(design, cell)?;
+            let polarization = index.subindex.unwrap_or(0);
+            if polarization >= polarization_n {
+                return Err(format!(
+                    "Cell {}-{} has no polarization {}",
+                    cell.layer, cell.cell, polarization
+                ));
+            }
+            Ok(SignalView {
+                samples: ViewSamples::Values(data.cells_data[index.index].data.as_ref()),
+                step: polarization_n,
+                offset: polarization,
+            })
+        }
+        SignalKind::Derived => Err("Derived signals are evaluated from their expression".into()),
+    }
+}
+
+/// A `[start, end)` range of samples, taking every `stride`-th one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SampleWindow {
+    pub start: usize,
+    pub end: usize,
+    pub stride: usize,
+}
+
+impl SampleWindow {
+    pub fn new(
+        start: Option<usize>,
+        end: Option<usize>,
+        stride: Option<usize>,
+        num_samples: usize,
+    ) -> Result<SampleWindow, String> {
+        let start = start.unwrap_or(0);
+        let end = end.unwrap_or(num_samples).min(num_samples);
+        let stride = stride.unwrap_or(1);
+
+        if start > end {
+            return Err(format!(
+                "Sample window start {} is past its end {}",
+                start, end
+            ));
+        }
+        if stride == 0 {
+            return Err("Sample window stride must be at least 1".into());
+        }
+
+        Ok(SampleWindow { start, end, stride })
+    }
+
+    pub fn len(&self) -> usize {
+        (self.end - self.start + self.stride - 1) / self.stride
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    pub fn indices(&self) -> impl Iterator<Item = usize> {
+        (self.start..self.end).step_by(self.stride)
+    }
+
+    pub fn apply(&self, samples: &[f64]) -> Vec<f64> {
+        let end = self.end.min(samples.len());
+        let start = self.start.min(end);
+        samples[start..end]
+            .iter()
+            .step_by(self.stride)
+            .copied()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sample_window_defaults_to_all_samples() {
+        let window = SampleWindow::new(None, None, None, 5).unwrap();
+        assert_eq!((window.start, window.end, window.stride), (0, 5, 1));
+        assert_eq!(window.len(), 5);
+        assert_eq!(window.indices().collect::<Vec<_>>(), [0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn sample_window_clamps_and_strides() {
+        let window = SampleWindow::new(Some(2), Some(100), Some(3), 10).unwrap();
+        assert_eq!(window.end, 10);
+        assert_eq!(window.len(), 3);
+        assert_eq!(window.indices().collect::<Vec<_>>(), [2, 5, 8]);
+
+        let samples: Vec<f64> = (0..10).map(|i| i as f64).collect();
+        assert_eq!(window.apply(&samples), [2.0, 5.0, 8.0]);
+        // Shorter data is cut at its end
+        assert_eq!(window.apply(&samples[..6]), [2.0, 5.0]);
+        assert!(window.apply(&samples[..1]).is_empty());
+    }
+
+    #[test]
+    fn windows_interleaved_views() {
+        // Two polarizations interleaved, the second one is 10 * i + 1
+        let samples: Vec<f64> = (0..10)
+            .flat_map(|i| [10.0 * i as f64, 10.0 * i as f64 + 1.0])
+            .collect();
+        let bytes: Vec<u8> = samples
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect();
+        let window = SampleWindow::new(Some(2), Some(9), Some(3), 10).unwrap();
+        for view in [
+            SignalView {
+                samples: ViewSamples::Values(&samples),
+                step: 2,
+                offset: 1,
+            },
+            SignalView::from_bytes(&bytes, 2, 1),
+        ] {
+            let windowed = view.window(&window);
+            assert_eq!(windowed.len(), window.len());
+            assert_eq!(windowed.to_vec(), [21.0, 51.0, 81.0]);
+        }
+    }
+
+    #[test]
+    fn sample_window_can_be_empty() {
+        let window = SampleWindow::new(Some(3), Some(3), None, 10).unwrap();
+        assert!(window.is_empty());
+        assert_eq!(window.len(), 0);
+        assert_eq!(window.indices().count(), 0);
+    }
+
+    #[test]
+    fn sample_window_rejects_invalid_bounds() {
+        assert!(SampleWindow::new(Some(5), Some(4), None, 10).is_err());
+        assert!(SampleWindow::new(Some(11), None, None, 10).is_err());
+        assert!(SampleWindow::new(None, None, Some(0), 10).is_err());
+    }
+}
@@ -0,0 +1,772 @@
+//! Arithmetic expressions over the signals of a simulation, evaluated into
+//! derived signals.
+//!
+//! `cell("A")` is the first polarization of the cell labelled `A` (or with
+//! index `0-3` for `cell("0-3")`), `cell("A")[1]` its second polarization
+//! and `clock(2)` a clock. Expressions combine them with `+ - * /`, the
+//! comparisons `< <= > >= == !=` (1 if true, 0 otherwise) and the functions
+//! `abs(x)`, `sqrt(x)`, `min(..)`, `max(..)` and `mean(x, n)`, the moving
+//! average over the last `n` samples.
+
+use crate::signal::{
+    stored_cell_index, SampleWindow, SignalIndex, SignalKind, SignalSource, CLOCK_COUNT,
+};
+use crate::sim_cache::SimulationCache;
+use qca_core::design::file::QCADesign;
+use qca_core::objects::cell::QCACellIndex;
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+use std::str::FromStr;
+use tauri::State;
+
+/// Samples evaluated at once by [`SignalExpr::eval_window`].
+const EVAL_CHUNK: usize = 1 << 16;
+
+/// A named expression, stored with the design so analyses can reuse it.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DerivedSignal {
+    pub name: String,
+    pub expression: String,
+    /// Clock zone whose hold phases the signal is read at in truth tables.
+    #[serde(default)]
+    pub clock_zone: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+}
+
+impl BinaryOp {
+    fn apply(&self, a: f64, b: f64) -> f64 {
+        let truth = |value: bool| value as u8 as f64;
+        match self {
+            BinaryOp::Add => a + b,
+            BinaryOp::Sub => a - b,
+            BinaryOp::Mul => a * b,
+            BinaryOp::Div => a / b,
+            BinaryOp::Lt => truth(a < b),
+            BinaryOp::Le => truth(a <= b),
+            BinaryOp::Gt => truth(a > b),
+            BinaryOp::Ge => truth(a >= b),
+            BinaryOp::Eq => truth(a == b),
+            BinaryOp::Ne => truth(a != b),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SignalExpr {
+    Number(f64),
+    Cell { cell: String, polarization: usize },
+    Clock(usize),
+    Neg(Box<SignalExpr>),
+    Binary(BinaryOp, Box<SignalExpr>, Box<SignalExpr>),
+    Abs(Box<SignalExpr>),
+    Sqrt(Box<SignalExpr>),
+    Min(Vec<SignalExpr>),
+    Max(Vec<SignalExpr>),
+    Mean(Box<SignalExpr>, usize),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(f64),
+    Ident(String),
+    Str(String),
+    Op(BinaryOp),
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+    Comma,
+}
+
+fn tokenize(expression: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut chars = expression.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' | ')' | '[' | ']' | ',' | '+' | '-' | '*' | '/' => {
+                chars.next();
+                tokens.push(match c {
+                    '(' => Token::LParen,
+                    ')' => Token::RParen,
+                    '[' => Token::LBracket,
+                    ']' => Token::RBracket,
+                    ',' => Token::Comma,
+                    '+' => Token::Op(BinaryOp::Add),
+                    '-' => Token::Op(BinaryOp::Sub),
+                    '*' => Token::Op(BinaryOp::Mul),
+                    _ => Token::Op(BinaryOp::Div),
+                });
+            }
+            '<' | '>' | '=' | '!' => {
+                chars.next();
+                let equals = chars.peek() == Some(&'=');
+                if equals {
+                    chars.next();
+                }
+                tokens.push(Token::Op(match (c, equals) {
+                    ('<', false) => BinaryOp::Lt,
+                    ('<', true) => BinaryOp::Le,
+                    ('>', false) => BinaryOp::Gt,
+                    ('>', true) => BinaryOp::Ge,
+                    ('=', true) => BinaryOp::Eq,
+                    ('!', true) => BinaryOp::Ne,
+                    _ => return Err(format!("Unexpected character '{}'", c)),
+                }));
+            }
+            '"' => {
+                chars.next();
+                let mut string = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => string.push(c),
+                        None => return Err("Unterminated string".into()),
+                    }
+                }
+                tokens.push(Token::Str(string));
+            }
+            c if c.is_ascii_digit() || c == '.' => {
+                let mut number = String::new();
+                while let Some(&c) = chars.peek() {
+                    let exponent_sign = (c == '-' || c == '+') && number.ends_with(['e', 'E']);
+                    if !(c.is_ascii_digit() || c == '.' || c == 'e' || c == 'E' || exponent_sign) {
+                        break;
+                    }
+                    number.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Number(
+                    number
+                        .parse()
+                        .map_err(|_err| format!("Invalid number '{}'", number))?,
+                ));
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let mut ident = String::new();
+                while let Some(&c) = chars.peek() {
+                    if !(c.is_alphanumeric() || c == '_') {
+                        break;
+                    }
+                    ident.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Ident(ident));
+            }
+            c => return Err(format!("Unexpected character '{}'", c)),
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn expect(&mut self, token: Token) -> Result<(), String> {
+        match self.next() {
+            Some(next) if next == token => Ok(()),
+            Some(next) => Err(format!("Expected {:?}, found {:?}", token, next)),
+            None => Err(format!("Expected {:?}, found end of expression", token)),
+        }
+    }
+
+    fn binary(
+        &mut self,
+        operators: &[BinaryOp],
+        operand: fn(&mut Parser) -> Result<SignalExpr, String>,
+    ) -> Result<SignalExpr, String> {
+        let mut expr = operand(self)?;
+        while let Some(Token::Op(op)) = self.peek() {
+            let op = *op;
+            if !operators.contains(&op) {
+                break;
+            }
+            self.next();
+            expr = SignalExpr::Binary(op, Box::new(expr), Box::new(operand(self)?));
+        }
+        Ok(expr)
+    }
+
+    fn comparison(&mut self) -> Result<SignalExpr, String> {
+        let operators = [
+            BinaryOp::Lt,
+            BinaryOp::Le,
+            BinaryOp::Gt,
+            BinaryOp::Ge,
+            BinaryOp::Eq,
+            BinaryOp::Ne,
+        ];
+        self.binary(&operators, Parser::additive)
+    }
+
+    fn additive(&mut self) -> Result<SignalExpr, String> {
+        self.binary(&[BinaryOp::Add, BinaryOp::Sub], Parser::term)
+    }
+
+    fn term(&mut self) -> Result<SignalExpr, String> {
+        self.binary(&[BinaryOp::Mul, BinaryOp::Div], Parser::unary)
+    }
+
+    fn unary(&mut self) -> Result<SignalExpr, String> {
+        if self.peek() == Some(&Token::Op(BinaryOp::Sub)) {
+            self.next();
+            return Ok(SignalExpr::Neg(Box::new(self.unary()?)));
+        }
+        self.primary()
+    }
+
+    fn arguments(&mut self) -> Result<Vec<SignalExpr>, String> {
+        self.expect(Token::LParen)?;
+        let mut arguments = vec![self.comparison()?];
+        while self.peek() == Some(&Token::Comma) {
+            self.next();
+            arguments.push(self.comparison()?);
+        }
+        self.expect(Token::RParen)?;
+        Ok(arguments)
+    }
+
+    /// A non-negative integer literal, e.g. a clock number.
+    fn index(&mut self) -> Result<usize, String> {
+        match self.next() {
+            Some(Token::Number(n)) if n >= 0.0 && n.fract() == 0.0 => Ok(n as usize),
+            Some(token) => Err(format!("Expected an index, found {:?}", token)),
+            None => Err("Expected an index, found end of expression".into()),
+        }
+    }
+
+    fn primary(&mut self) -> Result<SignalExpr, String> {
+        match self.next() {
+            Some(Token::Number(n)) => Ok(SignalExpr::Number(n)),
+            Some(Token::LParen) => {
+                let expr = self.comparison()?;
+                self.expect(Token::RParen)?;
+                Ok(expr)
+            }
+            Some(Token::Ident(function)) => match function.to_lowercase().as_str() {
+                "cell" => {
+                    self.expect(Token::LParen)?;
+                    let cell = match self.next() {
+                        Some(Token::Str(cell)) => cell,
+                        _ => return Err("cell() takes a quoted label or cell index".into()),
+                    };
+                    self.expect(Token::RParen)?;
+                    let mut polarization = 0;
+                    if self.peek() == Some(&Token::LBracket) {
+                        self.next();
+                        polarization = self.index()?;
+                        self.expect(Token::RBracket)?;
+                    }
+                    Ok(SignalExpr::Cell { cell, polarization })
+                }
+                "clock" => {
+                    self.expect(Token::LParen)?;
+                    let clock = self.index()?;
+                    self.expect(Token::RParen)?;
+                    if clock >= CLOCK_COUNT {
+                        return Err(format!("Clock {} does not exist", clock));
+                    }
+                    Ok(SignalExpr::Clock(clock))
+                }
+                "mean" => {
+                    self.expect(Token::LParen)?;
+                    let operand = self.comparison()?;
+                    self.expect(Token::Comma)?;
+                    let window = self.index()?;
+                    self.expect(Token::RParen)?;
+                    if window == 0 {
+                        return Err("mean() needs a window of at least 1 sample".into());
+                    }
+                    Ok(SignalExpr::Mean(Box::new(operand), window))
+                }
+                name @ ("abs" | "sqrt" | "min" | "max") => {
+                    let mut arguments = self.arguments()?;
+                    match name {
+                        "min" => Ok(SignalExpr::Min(arguments)),
+                        "max" => Ok(SignalExpr::Max(arguments)),
+                        _ => {
+                            if arguments.len() != 1 {
+                                return Err(format!("{}() takes one argument", name));
+                            }
+                            let operand = Box::new(arguments.remove(0));
+                            Ok(match name {
+                                "abs" => SignalExpr::Abs(operand),
+                                _ => SignalExpr::Sqrt(operand),
+                            })
+                        }
+                    }
+                }
+                _ => Err(format!("Unknown function '{}'", function)),
+            },
+            Some(token) => Err(format!("Unexpected {:?}", token)),
+            None => Err("Unexpected end of expression".into()),
+        }
+    }
+}
+
+/// Finds a cell by its label, or by its `layer-cell` index.
+fn find_cell(design: &QCADesign, name: &str) -> Result<QCACellIndex, String> {
+    for (l, layer) in design.layers.iter().enumerate() {
+        for (c, cell) in layer.cells.iter().enumerate() {
+            if cell.label.as_deref() == Some(name) {
+                return Ok(QCACellIndex { layer: l, cell: c });
+            }
+        }
+    }
+    QCACellIndex::from_str(name).map_err(|_err| format!("Unknown cell '{}'", name))
+}
+
+impl SignalExpr {
+    pub fn parse(expression: &str) -> Result<SignalExpr, String> {
+        let mut parser = Parser {
+            tokens: tokenize(expression)?,
+            pos: 0,
+        };
+        let expr = parser.comparison()?;
+        match parser.next() {
+            None => Ok(expr),
+            Some(token) => Err(format!("Unexpected {:?}", token)),
+        }
+    }
+
+    /// Evaluates the expression at the samples in `range`. Only those
+    /// samples are read, plus the ones moving averages look back on.
+    pub fn eval_range(
+        &self,
+        simulation: &dyn SignalSource,
+        range: Range<usize>,
+    ) -> Result<Vec<f64>, String> {
+        let (design, metadata) = (simulation.design(), simulation.metadata());
+        let end = range.end.min(metadata.num_samples);
+        let range = range.start.min(end)..end;
+        let read = |index: SignalIndex| {
+            let view = simulation.signal_view(&index)?;
+            Ok::<Vec<f64>, String>(
+                range
+                    .clone()
+                    .map(|sample| view.get(sample).unwrap_or(f64::NAN))
+                    .collect(),
+            )
+        };
+        let combine = |operands: &Vec<SignalExpr>, f: fn(f64, f64) -> f64| {
+            let mut values = operands
+                .iter()
+                .map(|operand| operand.eval_range(simulation, range.clone()))
+                .collect::<Result<Vec<_>, String>>()?
+                .into_iter();
+            let first = values.next().unwrap_or_default();
+            Ok::<Vec<f64>, String>(values.fold(first, |acc, values| {
+                acc.iter().zip(values).map(|(a, b)| f(*a, b)).collect()
+            }))
+        };
+        let unary = |operand: &SignalExpr, f: fn(f64) -> f64| {
+            Ok::<Vec<f64>, String>(
+                operand
+                    .eval_range(simulation, range.clone())?
+                    .into_iter()
+                    .map(f)
+                    .collect(),
+            )
+        };
+
+        Ok(match self {
+            SignalExpr::Number(n) => vec![*n; range.len()],
+            SignalExpr::Cell { cell, polarization } => {
+                let cell = find_cell(design, cell)?;
+                read(SignalIndex {
+                    kind: SignalKind::Cell,
+                    index: stored_cell_index(metadata, &cell)?,
+                    subindex: Some(*polarization),
+                })?
+            }
+            SignalExpr::Clock(clock) => read(SignalIndex {
+                kind: SignalKind::Clock,
+                index: *clock,
+                subindex: None,
+            })?,
+            SignalExpr::Neg(operand) => unary(operand, |v| -v)?,
+            SignalExpr::Binary(op, a, b) => a
+                .eval_range(simulation, range.clone())?
+                .iter()
+                .zip(b.eval_range(simulation, range.clone())?)
+                .map(|(a, b)| op.apply(*a, b))
+                .collect(),
+            SignalExpr::Abs(operand) => unary(operand, f64::abs)?,
+            SignalExpr::Sqrt(operand) => unary(operand, f64::sqrt)?,
+            SignalExpr::Min(operands) => combine(operands, f64::min)?,
+            SignalExpr::Max(operands) => combine(operands, f64::max)?,
+            SignalExpr::Mean(operand, window) => {
+                // Near the start of the run, the average is over the samples
+                // so far
+                let first = range.start - range.start.min(window - 1);
+                let values = operand.eval_range(simulation, first..range.end)?;
+                let mut sum = 0.0;
+                let mut averages = Vec::with_capacity(range.len());
+                for (i, value) in values.iter().enumerate() {
+                    sum += value;
+                    if i >= *window {
+                        sum -= values[i - window];
+                    }
+                    let sample = first + i;
+                    if sample >= range.start {
+                        averages.push(sum / (sample + 1).min(*window) as f64);
+                    }
+                }
+                averages
+            }
+        })
+    }
+
+    /// Evaluates the expression at the samples of a window, a chunk at a
+    /// time, so long runs are never evaluated whole.
+    pub fn eval_window(
+        &self,
+        simulation: &dyn SignalSource,
+        window: &SampleWindow,
+    ) -> Result<Vec<f64>, String> {
+        let chunk = (EVAL_CHUNK / window.stride).max(1) * window.stride;
+        let mut values = Vec::with_capacity(window.len());
+        for start in (window.start..window.end).step_by(chunk) {
+            let end = (start + chunk).min(window.end);
+            // Only the last sample of the chunk that is in the window is needed
+            let last = start + (end - start - 1) / window.stride * window.stride;
+            values.extend(
+                self.eval_range(simulation, start..last + 1)?
+                    .into_iter()
+                    .step_by(window.stride),
+            );
+        }
+        Ok(values)
+    }
+}
+
+/// Evaluates derived signals at the samples of a window, in order.
+pub fn evaluate_derived(
+    simulation: &dyn SignalSource,
+    derived: &[DerivedSignal],
+    window: &SampleWindow,
+) -> Result<Vec<Vec<f64>>, String> {
+    derived
+        .iter()
+        .map(|signal| {
+            SignalExpr::parse(&signal.expression)
+                .and_then(|expr| expr.eval_window(simulation, window))
+                .map_err(|err| format!("Derived signal '{}': {}", signal.name, err))
+        })
+        .collect()
+}
+
+/// Evaluates an expression over a window of samples, e.g. to preview a
+/// derived signal before saving it.
+#[tauri::command(async)]
+pub fn evaluate_signal_expression(
+    cache: State<'_, SimulationCache>,
+    filename: String,
+    expression: String,
+    start: Option<usize>,
+    end: Option<usize>,
+    stride: Option<usize>,
+) -> Result<Vec<f64>, String> {
+    let access = cache.access(&filename)?;
+    let simulation = access.source();
+    let window = SampleWindow::new(start, end, stride, simulation.metadata().num_samples)?;
+    SignalExpr::parse(&expression)?.eval_window(simulation, &window)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim_cache::LoadedSimulation;
+    use qca_core::simulation::file::{QCACellData, QCASimulationData};
+    use serde_json::json;
+
+    fn number(n: f64) -> Box<SignalExpr> {
+        Box::new(SignalExpr::Number(n))
+    }
+
+    fn binary(op: BinaryOp, a: Box<SignalExpr>, b: Box<SignalExpr>) -> Box<SignalExpr> {
+        Box::new(SignalExpr::Binary(op, a, b))
+    }
+
+    /// A run with a 4 dot cell labelled `A`, an 8 dot cell labelled `B` and
+    /// `clock(0)` counting up from 0.
+    fn simulation(num_samples: usize) -> LoadedSimulation {
+        let cell = |label: &str| {
+            json!({
+                "position": [0.0, 0.0],
+                "rotation": 0.0,
+                "typ": "Normal",
+                "clock_phase_shift": 0.0,
+                "dot_probability_distribution": [],
+                "label": label,
+            })
+        };
+        let architecture = |dot_count: u8| {
+            json!({
+                "side_length": 18.0,
+                "dot_diameter": 5.0,
+                "dot_count": dot_count,
+                "dot_positions": [],
+                "dot_tunnels": [],
+            })
+        };
+        let layer = |architecture: &str, cell: serde_json::Value| {
+            json!({
+                "name": architecture,
+                "visible": true,
+                "cell_architecture_id": architecture,
+                "cells": [cell],
+                "z_position": 0.0,
+            })
+        };
+        let design = serde_json::from_value(json!({
+            "qca_core_version": qca_core::QCA_CORE_VERSION,
+            "layers": [layer("binary", cell("A")), layer("ternary", cell("B"))],
+            "cell_architectures": {
+                "binary": architecture(4),
+                "ternary": architecture(8),
+            },
+            "simulation_settings": {
+                "selected_simulation_model_id": null,
+                "simulation_model_settings": {},
+            },
+        }))
+        .unwrap();
+        let metadata = serde_json::from_value(json!({
+            "qca_core_version": qca_core::QCA_CORE_VERSION,
+            "start_time": "2024-05-01T12:00:00+00:00",
+            "duration": { "seconds": 1, "nanoseconds": 0 },
+            "num_samples": num_samples,
+            "stored_cells": [{ "layer": 0, "cell": 0 }, { "layer": 1, "cell": 0 }],
+        }))
+        .unwrap();
+        let counting: Vec<f64> = (0..num_samples).map(|i| i as f64).collect();
+        LoadedSimulation {
+            design,
+            data: QCASimulationData {
+                metadata,
+                clock_data: [
+                    counting.clone(),
+                    vec![0.0; num_samples],
+                    vec![0.0; num_samples],
+                    vec![0.0; num_samples],
+                ],
+                cells_data: vec![
+                    QCACellData {
+                        data: vec![0.5; num_samples],
+                    },
+                    // Polarizations are interleaved: 1 then -1
+                    QCACellData {
+                        data: (0..2 * num_samples)
+                            .map(|i| if i % 2 == 0 { 1.0 } else { -1.0 })
+                            .collect(),
+                    },
+                ],
+            },
+        }
+    }
+
+    #[test]
+    fn multiplication_binds_tighter_than_addition() {
+        assert_eq!(
+            SignalExpr::parse("1 + 2 * 3").unwrap(),
+            *binary(
+                BinaryOp::Add,
+                number(1.0),
+                binary(BinaryOp::Mul, number(2.0), number(3.0))
+            )
+        );
+        assert_eq!(
+            SignalExpr::parse("(1 + 2) * 3").unwrap(),
+            *binary(
+                BinaryOp::Mul,
+                binary(BinaryOp::Add, number(1.0), number(2.0)),
+                number(3.0)
+            )
+        );
+        // Operators of the same precedence are left associative
+        assert_eq!(
+            SignalExpr::parse("8 - 4 - 2").unwrap(),
+            *binary(
+                BinaryOp::Sub,
+                binary(BinaryOp::Sub, number(8.0), number(4.0)),
+                number(2.0)
+            )
+        );
+    }
+
+    #[test]
+    fn unary_minus_binds_tighter_than_multiplication() {
+        assert_eq!(
+            SignalExpr::parse("-2 * 3").unwrap(),
+            *binary(
+                BinaryOp::Mul,
+                Box::new(SignalExpr::Neg(number(2.0))),
+                number(3.0)
+            )
+        );
+        assert_eq!(
+            SignalExpr::parse("--1").unwrap(),
+            SignalExpr::Neg(Box::new(SignalExpr::Neg(number(1.0))))
+        );
+        assert_eq!(SignalExpr::parse("1e-3").unwrap(), SignalExpr::Number(1e-3));
+    }
+
+    #[test]
+    fn comparisons_bind_loosest() {
+        assert_eq!(
+            SignalExpr::parse("1 + 1 >= 2").unwrap(),
+            *binary(
+                BinaryOp::Ge,
+                binary(BinaryOp::Add, number(1.0), number(1.0)),
+                number(2.0)
+            )
+        );
+        for (expression, op) in [
+            ("1 < 2", BinaryOp::Lt),
+            ("1 <= 2", BinaryOp::Le),
+            ("1 > 2", BinaryOp::Gt),
+            ("1 == 2", BinaryOp::Eq),
+            ("1 != 2", BinaryOp::Ne),
+        ] {
+            assert_eq!(
+                SignalExpr::parse(expression).unwrap(),
+                *binary(op, number(1.0), number(2.0))
+            );
+        }
+    }
+
+    #[test]
+    fn parses_signals_and_functions() {
+        assert_eq!(
+            SignalExpr::parse("cell(\"A\")[1]").unwrap(),
+            SignalExpr::Cell {
+                cell: "A".into(),
+                polarization: 1,
+            }
+        );
+        assert_eq!(
+            SignalExpr::parse("cell(\"0-3\")").unwrap(),
+            SignalExpr::Cell {
+                cell: "0-3".into(),
+                polarization: 0,
+            }
+        );
+        assert_eq!(
+            SignalExpr::parse("mean(abs(clock(2)), 10)").unwrap(),
+            SignalExpr::Mean(
+                Box::new(SignalExpr::Abs(Box::new(SignalExpr::Clock(2)))),
+                10
+            )
+        );
+        assert_eq!(
+            SignalExpr::parse("max(1, 2, 3)").unwrap(),
+            SignalExpr::Max(vec![
+                SignalExpr::Number(1.0),
+                SignalExpr::Number(2.0),
+                SignalExpr::Number(3.0)
+            ])
+        );
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        for expression in [
+            "",
+            "1 +",
+            "(1 + 2",
+            "1 2",
+            "1 = 2",
+            "1 # 2",
+            "cell(A)",
+            "cell(\"A\"",
+            "cell(\"A\")[1.5]",
+            "clock(4)",
+            "mean(clock(0), 0)",
+            "abs(1, 2)",
+            "foo(1)",
+            "\"unterminated",
+            "1..2",
+        ] {
+            assert!(
+                SignalExpr::parse(expression).is_err(),
+                "'{}' should not parse",
+                expression
+            );
+        }
+    }
+
+    #[test]
+    fn evaluates_cells_and_clocks() {
+        let simulation = simulation(4);
+        let eval = |expression: &str| {
+            SignalExpr::parse(expression)
+                .unwrap()
+                .eval_range(&simulation, 0..4)
+        };
+        assert_eq!(eval("cell(\"A\") * 2").unwrap(), [1.0; 4]);
+        assert_eq!(eval("cell(\"B\")[1]").unwrap(), [-1.0; 4]);
+        assert_eq!(eval("cell(\"1-0\")").unwrap(), [1.0; 4]);
+        assert_eq!(eval("clock(0) >= 2").unwrap(), [0.0, 0.0, 1.0, 1.0]);
+        assert!(eval("cell(\"A\")[1]").is_err());
+        assert!(eval("cell(\"C\")").is_err());
+    }
+
+    #[test]
+    fn moving_average_starts_over_the_samples_so_far() {
+        let simulation = simulation(6);
+        let mean = SignalExpr::parse("mean(clock(0), 3)").unwrap();
+        // Before the third sample the average is over fewer samples
+        assert_eq!(
+            mean.eval_range(&simulation, 0..6).unwrap(),
+            [0.0, 0.5, 1.0, 2.0, 3.0, 4.0]
+        );
+        // A range starting later still looks back on earlier samples
+        assert_eq!(mean.eval_range(&simulation, 1..3).unwrap(), [0.5, 1.0]);
+        assert_eq!(mean.eval_range(&simulation, 4..6).unwrap(), [3.0, 4.0]);
+        // Ranges are cut at the end of the run
+        assert_eq!(mean.eval_range(&simulation, 5..10).unwrap(), [4.0]);
+    }
+
+    #[test]
+    fn windows_match_the_full_run() {
+        let simulation = simulation(10);
+        let expr = SignalExpr::parse("mean(clock(0), 4) - clock(0)").unwrap();
+        let full = expr.eval_range(&simulation, 0..10).unwrap();
+        let window = SampleWindow::new(Some(3), Some(10), Some(3), 10).unwrap();
+        assert_eq!(
+            expr.eval_window(&simulation, &window).unwrap(),
+            window.apply(&full)
+        );
+    }
+}
@@ -0,0 +1,277 @@
+use crate::signal::{signal_view, SignalIndex, SignalSource, SignalView};
+use crate::sim_mmap::MappedSimulation;
+use qca_core::design::file::QCADesign;
+use qca_core::simulation::file::{QCASimulationData, QCASimulationMetadata};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use tauri::State;
+
+const DEFAULT_CACHE_BUDGET: usize = 1024 * 1024 * 1024;
+const MAX_MAPPED: usize = 8;
+
+pub struct LoadedSimulation {
+    pub design: QCADesign,
+    pub data: QCASimulationData,
+}
+
+impl SignalSource for LoadedSimulation {
+    fn design(&self) -> &QCADesign {
+        &self.design
+    }
+
+    fn metadata(&self) -> &QCASimulationMetadata {
+        &self.data.metadata
+    }
+
+    fn signal_view(&self, index: &SignalIndex) -> Result<SignalView<'_>, String> {
+        signal_view(&self.design, &self.data, index)
+    }
+}
+
+/// A simulation that is either parsed into the cache or mapped from its
+/// file.
+#[derive(Clone)]
+pub enum SimulationAccess {
+    Loaded(Arc<LoadedSimulation>),
+    Mapped(Arc<MappedSimulation>),
+}
+
+impl SimulationAccess {
+    pub fn source(&self) -> &dyn SignalSource {
+        match self {
+            SimulationAccess::Loaded(simulation) => simulation.as_ref(),
+            SimulationAccess::Mapped(simulation) => simulation.as_ref(),
+        }
+    }
+}
+
+struct SimulationCacheEntry {
+    modified: SystemTime,
+    simulation: Arc<LoadedSimulation>,
+    size: usize,
+    last_used: u64,
+}
+
+struct MappedCacheEntry {
+    modified: SystemTime,
+    simulation: Arc<MappedSimulation>,
+    last_used: u64,
+}
+
+struct SimulationCacheContext {
+    entries: HashMap<PathBuf, SimulationCacheEntry>,
+    /// Files too large for the budget. Mappings take no memory of their
+    /// own, but each holds the file open, so only `MAX_MAPPED` are kept.
+    mapped: HashMap<PathBuf, MappedCacheEntry>,
+    budget: usize,
+    tick: u64,
+}
+
+impl SimulationCacheContext {
+    fn used(&self) -> usize {
+        self.entries.values().map(|entry| entry.size).sum()
+    }
+
+    /// Drops the least recently used entries until the cache fits its budget.
+    /// The entry at `keep` is never evicted.
+    fn evict(&mut self, keep: Option<&Path>) {
+        while self.used() > self.budget {
+            let lru = self
+                .entries
+                .iter()
+                .filter(|(path, _)| Some(path.as_path()) != keep)
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(path, _)| path.clone());
+            match lru {
+                Some(path) => {
+                    log::debug!("Evicting cached simulation {}", path.display());
+                    self.entries.remove(&path);
+                }
+                None => break,
+            }
+        }
+    }
+
+    /// Drops the least recently used mappings until at most `MAX_MAPPED`
+    /// are left. The mapping at `keep` is never dropped.
+    fn evict_mapped(&mut self, keep: &Path) {
+        while self.mapped.len() > MAX_MAPPED {
+            let lru = self
+                .mapped
+                .iter()
+                .filter(|(path, _)| path.as_path() != keep)
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(path, _)| path.clone());
+            match lru {
+                Some(path) => {
+                    log::debug!("Unmapping simulation {}", path.display());
+                    self.mapped.remove(&path);
+                }
+                None => break,
+            }
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct SimulationCacheStats {
+    entries: Vec<String>,
+    used_bytes: usize,
+    budget_bytes: usize,
+}
+
+/// Parsed simulation files keyed by canonical path. An entry is reused only
+/// while the file's modification time matches the one it was parsed with.
+/// Simulations larger than the budget are mapped instead of parsed.
+pub struct SimulationCache {
+    context: Mutex<SimulationCacheContext>,
+}
+
+impl SimulationCache {
+    pub fn new() -> SimulationCache {
+        SimulationCache {
+            context: Mutex::new(SimulationCacheContext {
+                entries: HashMap::new(),
+                mapped: HashMap::new(),
+                budget: DEFAULT_CACHE_BUDGET,
+                tick: 0,
+            }),
+        }
+    }
+
+    /// A simulation parsed into the cache, or the file mapped into memory if
+    /// it does not fit the budget, so that it is read in bounded memory
+    /// whatever its size.
+    pub fn access(&self, filename: &str) -> Result<SimulationAccess, String> {
+        let path = std::fs::canonicalize(filename).map_err(|_err| "File cannot be opened")?;
+        let modified = std::fs::metadata(&path)
+            .and_then(|metadata| metadata.modified())
+            .map_err(|_err| "File cannot be opened")?;
+
+        {
+            let mut context = self.context.lock().map_err(|_err| "Cache is poisoned")?;
+            context.tick += 1;
+            let tick = context.tick;
+            if let Some(entry) = context.entries.get_mut(&path) {
+                if entry.modified == modified {
+                    entry.last_used = tick;
+                    return Ok(SimulationAccess::Loaded(entry.simulation.clone()));
+                }
+            }
+            if let Some(entry) = context.mapped.get_mut(&path) {
+                if entry.modified == modified {
+                    entry.last_used = tick;
+                    return Ok(SimulationAccess::Mapped(entry.simulation.clone()));
+                }
+            }
+        }
+
+        // Read without holding the lock, so other files stay available. The
+        // file was migrated when it was opened; the header is read migrated
+        // in case it could not be rewritten.
+        let mapped = MappedSimulation::open(filename)?;
+        let size = mapped.memory_size();
+        let budget = self
+            .context
+            .lock()
+            .map_err(|_err| "Cache is poisoned")?
+            .budget;
+        if size > budget {
+            // Caching it would evict everything else and still exceed the
+            // budget
+            log::debug!("Mapping {}, it exceeds the budget", path.display());
+            let simulation = Arc::new(mapped);
+            let mut context = self.context.lock().map_err(|_err| "Cache is poisoned")?;
+            context.tick += 1;
+            let last_used = context.tick;
+            context.entries.remove(&path);
+            context.mapped.insert(
+                path.clone(),
+                MappedCacheEntry {
+                    modified,
+                    simulation: simulation.clone(),
+                    last_used,
+                },
+            );
+            context.evict_mapped(&path);
+            return Ok(SimulationAccess::Mapped(simulation));
+        }
+
+        let simulation = Arc::new(mapped.load()?);
+        let mut context = self.context.lock().map_err(|_err| "Cache is poisoned")?;
+        context.tick += 1;
+        let last_used = context.tick;
+        context.mapped.remove(&path);
+        context.entries.insert(
+            path.clone(),
+            SimulationCacheEntry {
+                modified,
+                simulation: simulation.clone(),
+                size,
+                last_used,
+            },
+        );
+        context.evict(Some(&path));
+
+        Ok(SimulationAccess::Loaded(simulation))
+    }
+
+    pub fn close(&self, filename: &str) -> Result<bool, String> {
+        let path = std::fs::canonicalize(filename).map_err(|_err| "File cannot be opened")?;
+        let mut context = self.context.lock().map_err(|_err| "Cache is poisoned")?;
+        let mapped = context.mapped.remove(&path).is_some();
+        Ok(context.entries.remove(&path).is_some() || mapped)
+    }
+
+    pub fn set_budget(&self, budget: usize) -> Result<(), String> {
+        let mut context = self.context.lock().map_err(|_err| "Cache is poisoned")?;
+        context.budget = budget;
+        context.evict(None);
+        Ok(())
+    }
+
+    pub fn stats(&self) -> Result<SimulationCacheStats, String> {
+        let context = self.context.lock().map_err(|_err| "Cache is poisoned")?;
+        Ok(SimulationCacheStats {
+            entries: context
+                .entries
+                .keys()
+                .map(|path| path.display().to_string())
+                .collect(),
+            used_bytes: context.used(),
+            budget_bytes: context.budget,
+        })
+    }
+}
+
+impl Default for SimulationCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[tauri::command]
+pub fn close_simulation(
+    cache: State<'_, SimulationCache>,
+    filename: String,
+) -> Result<bool, String> {
+    cache.close(&filename)
+}
+
+#[tauri::command]
+pub fn set_simulation_cache_budget(
+    cache: State<'_, SimulationCache>,
+    budget_bytes: usize,
+) -> Result<(), String> {
+    cache.set_budget(budget_bytes)
+}
+
+#[tauri::command]
+pub fn get_simulation_cache_stats(
+    cache: State<'_, SimulationCache>,
+) -> Result<SimulationCacheStats, String> {
+    cache.stats()
+}
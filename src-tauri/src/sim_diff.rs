@@ -0,0 +1,314 @@
+use crate::clocking::{cell_clock_zone, clock_holds, row_sample};
+use crate::digital::{digitize, DigitizeOptions, LogicValue};
+use crate::signal::{cell_name, list_signals, SignalDescriptor, SignalIndex, SignalSource};
+use crate::sim_cache::SimulationCache;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::ops::Range;
+use tauri::State;
+
+#[derive(Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiffAlignment {
+    CellIndex,
+    Label,
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct DiffOptions {
+    align_by: DiffAlignment,
+    /// Largest absolute deviation that still counts as equal.
+    tolerance: f64,
+    /// How many exceeding samples to list per signal.
+    max_exceedances: usize,
+    clock_threshold: f64,
+    logical_threshold: f64,
+    value_threshold: f64,
+}
+
+impl Default for DiffOptions {
+    fn default() -> Self {
+        DiffOptions {
+            align_by: DiffAlignment::CellIndex,
+            tolerance: 1e-3,
+            max_exceedances: 10,
+            clock_threshold: 0.05,
+            logical_threshold: 0.01,
+            value_threshold: 0.8,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct SignalDiff {
+    name: String,
+    a: SignalIndex,
+    b: SignalIndex,
+    max_deviation: f64,
+    rms_deviation: f64,
+    /// First samples at which the deviation exceeds the tolerance.
+    exceedances: Vec<usize>,
+    /// Whether the values read for the truth table differ once digitised.
+    /// Always false for clocks.
+    digital_mismatch: bool,
+}
+
+#[derive(Serialize)]
+pub struct SimulationDiff {
+    num_samples: [usize; 2],
+    signals: Vec<SignalDiff>,
+    /// Signals found in only one of the simulations.
+    unmatched_a: Vec<String>,
+    unmatched_b: Vec<String>,
+    truth_tables_differ: bool,
+    passed: bool,
+}
+
+impl SimulationDiff {
+    pub fn passed(&self) -> bool {
+        self.passed
+    }
+}
+
+fn alignment_key(
+    simulation: &dyn SignalSource,
+    signal: &SignalDescriptor,
+    align_by: DiffAlignment,
+) -> String {
+    match (signal.cell, align_by) {
+        (Some(cell), DiffAlignment::CellIndex) => format!(
+            "{}-{}:{}",
+            cell.layer,
+            cell.cell,
+            signal.polarization_index()
+        ),
+        (Some(cell), DiffAlignment::Label) => format!(
+            "{}:{}",
+            cell_name(simulation.design(), &cell),
+            signal.polarization_index()
+        ),
+        (None, _) => signal.name.clone(),
+    }
+}
+
+/// Digitised values of a cell signal at the end of every hold phase of its
+/// clock zone.
+fn hold_values(
+    simulation: &dyn SignalSource,
+    signal: &SignalDescriptor,
+    holds: &[Vec<Range<usize>>],
+    options: &DigitizeOptions,
+) -> Result<Vec<LogicValue>, String> {
+    let cell = match signal.cell {
+        Some(cell) => cell,
+        None => return Ok(vec![]),
+    };
+    let view = simulation.signal_view(&signal.index)?;
+    let zone = cell_clock_zone(simulation.design(), &cell)?;
+    let holds = holds
+        .get(zone)
+        .ok_or(format!("Clock zone {} does not exist", zone))?;
+    Ok((0..holds.len())
+        .filter_map(|row| row_sample(holds, row, 0))
+        .map(|sample| match view.get(sample) {
+            Some(polarization) => digitize(polarization, LogicValue::Unknown, options),
+            None => LogicValue::Unknown,
+        })
+        .collect())
+}
+
+pub fn diff(
+    a: &dyn SignalSource,
+    b: &dyn SignalSource,
+    options: &DiffOptions,
+) -> Result<SimulationDiff, String> {
+    let signals_a = list_signals(a.design(), a.metadata())?;
+    let signals_b = list_signals(b.design(), b.metadata())?;
+    let mut keyed_b: HashMap<String, &SignalDescriptor> = signals_b
+        .iter()
+        .map(|signal| (alignment_key(b, signal, options.align_by), signal))
+        .collect();
+
+    let holds_a = clock_holds(a, options.clock_threshold)?;
+    let holds_b = clock_holds(b, options.clock_threshold)?;
+    let digitize_options = DigitizeOptions {
+        logical_threshold: options.logical_threshold,
+        value_threshold: options.value_threshold,
+        hysteresis: 0.0,
+    };
+
+    let mut signals = vec![];
+    let mut unmatched_a = vec![];
+    for signal_a in &signals_a {
+        let signal_b = match keyed_b.remove(&alignment_key(a, signal_a, options.align_by)) {
+            Some(signal_b) => signal_b,
+            None => {
+                unmatched_a.push(signal_a.name.clone());
+                continue;
+            }
+        };
+        let view_a = a.signal_view(&signal_a.index)?;
+        let view_b = b.signal_view(&signal_b.index)?;
+
+        let (mut max_deviation, mut square_sum, mut count) = (0.0_f64, 0.0, 0);
+        let mut exceedances = vec![];
+        for (i, (value_a, value_b)) in view_a.iter().zip(view_b.iter()).enumerate() {
+            let deviation = (value_a - value_b).abs();
+            max_deviation = max_deviation.max(deviation);
+            square_sum += deviation * deviation;
+            count += 1;
+            if deviation > options.tolerance && exceedances.len() < options.max_exceedances {
+                exceedances.push(i);
+            }
+        }
+
+        signals.push(SignalDiff {
+            name: signal_a.name.clone(),
+            a: signal_a.index,
+            b: signal_b.index,
+            max_deviation,
+            rms_deviation: match count {
+                0 => 0.0,
+                n => (square_sum / n as f64).sqrt(),
+            },
+            exceedances,
+            digital_mismatch: hold_values(a, signal_a, &holds_a, &digitize_options)?
+                != hold_values(b, signal_b, &holds_b, &digitize_options)?,
+        });
+    }
+    let mut unmatched_b: Vec<String> = keyed_b
+        .into_values()
+        .map(|signal| signal.name.clone())
+        .collect();
+    unmatched_b.sort();
+
+    let num_samples = [a.metadata().num_samples, b.metadata().num_samples];
+    let truth_tables_differ = signals.iter().any(|signal| signal.digital_mismatch);
+    let passed = num_samples[0] == num_samples[1]
+        && unmatched_a.is_empty()
+        && unmatched_b.is_empty()
+        && !truth_tables_differ
+        && signals.iter().all(|signal| signal.exceedances.is_empty());
+
+    Ok(SimulationDiff {
+        num_samples,
+        signals,
+        unmatched_a,
+        unmatched_b,
+        truth_tables_differ,
+        passed,
+    })
+}
+
+/// Compares two simulation files signal by signal, for regression checks
+/// after qca-core or model settings change.
+#[tauri::command(async)]
+pub fn diff_simulations(
+    cache: State<'_, SimulationCache>,
+    a: String,
+    b: String,
+    options: Option<DiffOptions>,
+) -> Result<SimulationDiff, String> {
+    let simulation_a = cache.access(&a)?;
+    let simulation_b = cache.access(&b)?;
+    diff(
+        simulation_a.source(),
+        simulation_b.source(),
+        &options.unwrap_or_default(),
+    )
+}
+
+fn diff_cli_usage() -> i32 {
+    let defaults = DiffOptions::default();
+    eprintln!("Usage: qca-forge diff-sim <a.qcs> <b.qcs> [options]");
+    eprintln!("Options:");
+    eprintln!("  --align-by cell_index|label   How signals are matched (cell_index)");
+    eprintln!(
+        "  --tolerance <value>           Largest equal deviation ({})",
+        defaults.tolerance
+    );
+    eprintln!(
+        "  --max-exceedances <count>     Exceeding samples listed per signal ({})",
+        defaults.max_exceedances
+    );
+    eprintln!(
+        "  --clock-threshold <value>     Clock hold threshold ({})",
+        defaults.clock_threshold
+    );
+    eprintln!(
+        "  --logical-threshold <value>   Logic level threshold ({})",
+        defaults.logical_threshold
+    );
+    eprintln!(
+        "  --value-threshold <value>     Settled polarization threshold ({})",
+        defaults.value_threshold
+    );
+    2
+}
+
+/// Runs `diff-sim` from the command line, printing the report as JSON.
+/// Returns the exit code: 0 if the simulations match, 1 if they differ and
+/// 2 on errors.
+pub fn run_diff_cli(args: &[String]) -> i32 {
+    let mut files = vec![];
+    let mut options = DiffOptions::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let number = match arg.as_str() {
+            "--tolerance" => Some(&mut options.tolerance),
+            "--clock-threshold" => Some(&mut options.clock_threshold),
+            "--logical-threshold" => Some(&mut options.logical_threshold),
+            "--value-threshold" => Some(&mut options.value_threshold),
+            _ => None,
+        };
+        if let Some(number) = number {
+            match args.next().and_then(|value| value.parse().ok()) {
+                Some(value) => *number = value,
+                None => return diff_cli_usage(),
+            }
+            continue;
+        }
+
+        match arg.as_str() {
+            "--max-exceedances" => match args.next().and_then(|value| value.parse().ok()) {
+                Some(count) => options.max_exceedances = count,
+                None => return diff_cli_usage(),
+            },
+            "--align-by" => match args.next().map(String::as_str) {
+                Some("cell_index") => options.align_by = DiffAlignment::CellIndex,
+                Some("label") => options.align_by = DiffAlignment::Label,
+                _ => return diff_cli_usage(),
+            },
+            file => files.push(file.to_string()),
+        }
+    }
+    if files.len() != 2 {
+        return diff_cli_usage();
+    }
+
+    let cache = SimulationCache::new();
+    let result = cache.access(&files[0]).and_then(|a| {
+        let b = cache.access(&files[1])?;
+        diff(a.source(), b.source(), &options)
+    });
+    match result {
+        Ok(report) => {
+            match serde_json::to_string_pretty(&report) {
+                Ok(json) => println!("{}", json),
+                Err(err) => {
+                    eprintln!("Failed to write report: {}", err);
+                    return 2;
+                }
+            }
+            match report.passed() {
+                true => 0,
+                false => 1,
+            }
+        }
+        Err(err) => {
+            eprintln!("{}", err);
+            2
+        }
+    }
+}
@@ -0,0 +1,203 @@
+//! Layout of .qcs simulation files, as written by qca-core's
+//! `write_to_file`.
+//!
+//! A simulation file is a tar archive with three entries:
+//!
+//! ```text
+//! design    the QCADesign as JSON
+//! metadata  the QCASimulationMetadata as JSON
+//! samples   little-endian f64 samples: the four clocks one after the other,
+//!           then the data of every cell in `stored_cells` order
+//! ```
+//!
+//! Every clock holds `num_samples` samples. A cell holds `num_samples`
+//! samples per polarization, interleaved by polarization. qca-core does not
+//! describe its layout, so this module is the one place that does; the
+//! round trip test of `sim_mmap` checks it against `write_to_file`.
+
+use crate::signal::{polarization_count, CLOCK_COUNT};
+use qca_core::design::file::QCADesign;
+use qca_core::simulation::file::QCASimulationMetadata;
+use serde::Serialize;
+
+pub const DESIGN_ENTRY: &str = "design";
+pub const METADATA_ENTRY: &str = "metadata";
+pub const SAMPLES_ENTRY: &str = "samples";
+const SAMPLE_SIZE: u64 = std::mem::size_of::<f64>() as u64;
+
+/// An entry of a tar archive and where its contents are.
+#[derive(Clone, Serialize)]
+pub struct ArchiveEntry {
+    pub path: String,
+    /// Byte offset of the entry's contents in the file.
+    pub offset: u64,
+    pub size: u64,
+}
+
+/// Where the samples of a signal are stored.
+#[derive(Clone)]
+pub struct SampleLayout {
+    /// Byte offset of the first sample in the file.
+    pub offset: u64,
+    /// Bytes from one sample to the next.
+    pub stride: u64,
+    /// Bytes from the start of the first sample to the end of the last.
+    pub span: u64,
+}
+
+/// Sample layouts of the clocks and of every polarization of every stored
+/// cell.
+pub struct SimulationLayout {
+    pub clocks: Vec<SampleLayout>,
+    pub cells: Vec<Vec<SampleLayout>>,
+}
+
+/// Where a file with this design and metadata stores its samples.
+pub fn sample_layout(
+    design: &QCADesign,
+    metadata: &QCASimulationMetadata,
+    entries: &[ArchiveEntry],
+) -> Result<SimulationLayout, String> {
+    let samples = entries
+        .iter()
+        .find(|entry| entry.path == SAMPLES_ENTRY)
+        .ok_or("Simulation file has no sample data")?;
+    let num_samples = metadata.num_samples as u64;
+    let span = |stride: u64| match num_samples {
+        0 => 0,
+        n => (n - 1) * stride + SAMPLE_SIZE,
+    };
+
+    let mut offset = samples.offset;
+    let mut clocks = vec![];
+    for _ in 0..CLOCK_COUNT {
+        clocks.push(SampleLayout {
+            offset,
+            stride: SAMPLE_SIZE,
+            span: span(SAMPLE_SIZE),
+        });
+        offset += num_samples * SAMPLE_SIZE;
+    }
+    let mut cells = vec![];
+    for cell in &metadata.stored_cells {
+        let polarization_n = polarization_count(design, cell)? as u64;
+        let stride = polarization_n * SAMPLE_SIZE;
+        cells.push(
+            (0..polarization_n)
+                .map(|polarization| SampleLayout {
+                    offset: offset + polarization * SAMPLE_SIZE,
+                    stride,
+                    span: span(stride),
+                })
+                .collect(),
+        );
+        offset += num_samples * stride;
+    }
+
+    if offset - samples.offset != samples.size {
+        return Err(format!(
+            "Simulation file holds {} bytes of samples, its metadata describes {}",
+            samples.size,
+            offset - samples.offset
+        ));
+    }
+    Ok(SimulationLayout { clocks, cells })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use qca_core::objects::cell::QCACellIndex;
+    use serde_json::json;
+
+    fn design() -> QCADesign {
+        let architecture = |dot_count: u8| {
+            json!({
+                "side_length": 18.0,
+                "dot_diameter": 5.0,
+                "dot_count": dot_count,
+                "dot_positions": [],
+                "dot_tunnels": [],
+            })
+        };
+        let layer = |architecture: &str| {
+            json!({
+                "name": architecture,
+                "visible": true,
+                "cell_architecture_id": architecture,
+                "cells": [{
+                    "position": [0.0, 0.0],
+                    "rotation": 0.0,
+                    "typ": "Normal",
+                    "clock_phase_shift": 0.0,
+                    "dot_probability_distribution": [],
+                    "label": null,
+                }],
+                "z_position": 0.0,
+            })
+        };
+        serde_json::from_value(json!({
+            "qca_core_version": qca_core::QCA_CORE_VERSION,
+            "layers": [layer("binary"), layer("ternary")],
+            "cell_architectures": {
+                "binary": architecture(4),
+                "ternary": architecture(8),
+            },
+            "simulation_settings": {
+                "selected_simulation_model_id": null,
+                "simulation_model_settings": {},
+            },
+        }))
+        .unwrap()
+    }
+
+    fn metadata(num_samples: usize) -> QCASimulationMetadata {
+        serde_json::from_value(json!({
+            "qca_core_version": qca_core::QCA_CORE_VERSION,
+            "start_time": "2024-05-01T12:00:00+00:00",
+            "duration": { "seconds": 1, "nanoseconds": 0 },
+            "num_samples": num_samples,
+            "stored_cells": [
+                QCACellIndex { layer: 1, cell: 0 },
+                QCACellIndex { layer: 0, cell: 0 },
+            ],
+        }))
+        .unwrap()
+    }
+
+    fn samples_entry(size: u64) -> Vec<ArchiveEntry> {
+        vec![ArchiveEntry {
+            path: SAMPLES_ENTRY.into(),
+            offset: 1024,
+            size,
+        }]
+    }
+
+    #[test]
+    fn lays_out_clocks_then_interleaved_cells() {
+        // 4 clocks, 2 polarizations and 1 polarization of 10 samples
+        let entries = samples_entry((4 + 2 + 1) * 10 * 8);
+        let layout = sample_layout(&design(), &metadata(10), &entries).unwrap();
+
+        let offsets = |layouts: &[SampleLayout]| -> Vec<(u64, u64)> {
+            layouts
+                .iter()
+                .map(|layout| (layout.offset, layout.stride))
+                .collect()
+        };
+        assert_eq!(
+            offsets(&layout.clocks),
+            [(1024, 8), (1104, 8), (1184, 8), (1264, 8)]
+        );
+        assert_eq!(offsets(&layout.cells[0]), [(1344, 16), (1352, 16)]);
+        assert_eq!(offsets(&layout.cells[1]), [(1504, 8)]);
+        assert_eq!(layout.cells[0][1].span, 9 * 16 + 8);
+    }
+
+    #[test]
+    fn rejects_sample_data_of_another_size() {
+        let entries = samples_entry((4 + 2 + 1) * 10 * 8 - 8);
+        assert!(sample_layout(&design(), &metadata(10), &entries).is_err());
+        assert!(sample_layout(&design(), &metadata(10), &[]).is_err());
+    }
+}
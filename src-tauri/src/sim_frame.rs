@@ -0,0 +1,325 @@
+//! Binary framing used by the `load-sim` protocol.
+//!
+//! A frame starts with a fixed size header, followed by a signal table and
+//! the sample data. All multi-byte values are written little-endian and
+//! every data block starts on an 8 byte boundary, so the frontend can view
+//! it directly as a typed array.
+//!
+//! ```text
+//! header       magic "QCSF", version u16, endianness u8 (0 = little), reserved u8,
+//!              num_samples u64, num_signals u32, data_offset u32,
+//!              first_sample u64, stride u64, total_samples u64
+//! signal entry kind u8, dtype u8, polarization u16, layer u32, cell u32,
+//!              offset u64, length u64, name_len u16, name [u8; name_len]
+//! data         signal blocks at their `offset` from the start of the frame
+//! ```
+//!
+//! `num_samples` counts the samples in the frame, which were taken every
+//! `stride` samples starting at `first_sample` out of the `total_samples` of
+//! the simulation. Clock signals store `u32::MAX` as their layer and the clock number as
+//! their cell.
+
+use crate::signal::{SampleWindow, SignalDescriptor, SignalKind, SignalView};
+use std::str::FromStr;
+
+pub const FRAME_MAGIC: &[u8; 4] = b"QCSF";
+pub const FRAME_VERSION: u16 = 1;
+const FRAME_HEADER_SIZE: usize = 48;
+const FRAME_SIGNAL_ENTRY_SIZE: usize = 30;
+const FRAME_ALIGNMENT: usize = 8;
+const FRAME_LITTLE_ENDIAN: u8 = 0;
+const CLOCK_LAYER: u32 = u32::MAX;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum SampleType {
+    F64 = 0,
+    F32 = 1,
+}
+
+impl SampleType {
+    pub fn size(&self) -> usize {
+        match self {
+            SampleType::F64 => std::mem::size_of::<f64>(),
+            SampleType::F32 => std::mem::size_of::<f32>(),
+        }
+    }
+}
+
+impl FromStr for SampleType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "f64" => Ok(SampleType::F64),
+            "f32" => Ok(SampleType::F32),
+            _ => Err(format!("Unsupported sample type '{}'", s)),
+        }
+    }
+}
+
+pub struct SimulationFrame {
+    window: SampleWindow,
+    total_samples: usize,
+    sample_type: SampleType,
+    signals: Vec<(SignalDescriptor, Vec<f64>)>,
+}
+
+impl SimulationFrame {
+    pub fn new(
+        window: SampleWindow,
+        total_samples: usize,
+        sample_type: SampleType,
+    ) -> SimulationFrame {
+        SimulationFrame {
+            window,
+            total_samples,
+            sample_type,
+            signals: Vec::new(),
+        }
+    }
+
+    /// Adds a signal whose samples were already taken at the window's
+    /// samples.
+    pub fn push_samples(&mut self, descriptor: SignalDescriptor, samples: Vec<f64>) {
+        self.signals.push((descriptor, samples));
+    }
+
+    /// Adds a signal, reading only the samples inside the frame's window.
+    pub fn push_view(&mut self, descriptor: SignalDescriptor, view: &SignalView) {
+        let samples = self
+            .window
+            .indices()
+            .map_while(|sample| view.get(sample))
+            .collect();
+        self.signals.push((descriptor, samples));
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let table_size: usize = self
+            .signals
+            .iter()
+            .map(|(descriptor, _)| FRAME_SIGNAL_ENTRY_SIZE + descriptor.name.len())
+            .sum();
+        let data_offset = align(FRAME_HEADER_SIZE + table_size);
+
+        let mut offsets = Vec::with_capacity(self.signals.len());
+        let mut frame_size = data_offset;
+        for (_, samples) in &self.signals {
+            offsets.push(frame_size);
+            frame_size = align(frame_size + samples.len() * self.sample_type.size());
+        }
+
+        let mut bytes = Vec::with_capacity(frame_size);
+        bytes.extend_from_slice(FRAME_MAGIC);
+        bytes.extend_from_slice(&FRAME_VERSION.to_le_bytes());
+        bytes.push(FRAME_LITTLE_ENDIAN);
+        bytes.push(0);
+        bytes.extend_from_slice(&(self.window.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(&(self.signals.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&(data_offset as u32).to_le_bytes());
+        bytes.extend_from_slice(&(self.window.start as u64).to_le_bytes());
+        bytes.extend_from_slice(&(self.window.stride as u64).to_le_bytes());
+        bytes.extend_from_slice(&(self.total_samples as u64).to_le_bytes());
+
+        for ((descriptor, samples), offset) in self.signals.iter().zip(&offsets) {
+            let (layer, cell) = match (descriptor.index.kind, descriptor.cell) {
+                (SignalKind::Cell, Some(cell)) => (cell.layer as u32, cell.cell as u32),
+                _ => (CLOCK_LAYER, descriptor.index.index as u32),
+            };
+            let name = descriptor.name.as_bytes();
+
+            bytes.push(descriptor.index.kind as u8);
+            bytes.push(self.sample_type as u8);
+            bytes.extend_from_slice(&(descriptor.polarization_index() as u16).to_le_bytes());
+            bytes.extend_from_slice(&layer.to_le_bytes());
+            bytes.extend_from_slice(&cell.to_le_bytes());
+            bytes.extend_from_slice(&(*offset as u64).to_le_bytes());
+            bytes.extend_from_slice(&(samples.len() as u64).to_le_bytes());
+            bytes.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            bytes.extend_from_slice(name);
+        }
+
+        for (_, samples) in &self.signals {
+            bytes.resize(align(bytes.len()), 0);
+            match self.sample_type {
+                SampleType::F64 => samples
+                    .iter()
+                    .for_each(|value| bytes.extend_from_slice(&value.to_le_bytes())),
+                SampleType::F32 => samples
+                    .iter()
+                    .for_each(|value| bytes.extend_from_slice(&(*value as f32).to_le_bytes())),
+            }
+        }
+        bytes.resize(frame_size, 0);
+
+        bytes
+    }
+}
+
+fn align(offset: usize) -> usize {
+    (offset + FRAME_ALIGNMENT - 1) / FRAME_ALIGNMENT * FRAME_ALIGNMENT
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signal::SignalIndex;
+    use qca_core::objects::cell::QCACellIndex;
+
+    fn u16_at(bytes: &[u8], at: usize) -> u16 {
+        u16::from_le_bytes(bytes[at..at + 2].try_into().unwrap())
+    }
+
+    fn u32_at(bytes: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+    }
+
+    fn u64_at(bytes: &[u8], at: usize) -> u64 {
+        u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+    }
+
+    struct DecodedSignal {
+        kind: u8,
+        dtype: u8,
+        polarization: u16,
+        layer: u32,
+        cell: u32,
+        name: String,
+        samples: Vec<f64>,
+    }
+
+    /// Decodes a frame the way the frontend does.
+    fn decode(bytes: &[u8]) -> ([u64; 4], Vec<DecodedSignal>) {
+        assert_eq!(&bytes[0..4], FRAME_MAGIC);
+        assert_eq!(u16_at(bytes, 4), FRAME_VERSION);
+        assert_eq!(bytes[6], FRAME_LITTLE_ENDIAN);
+        let num_signals = u32_at(bytes, 16) as usize;
+        let data_offset = u32_at(bytes, 20) as usize;
+        assert_eq!(data_offset % FRAME_ALIGNMENT, 0);
+        let header = [
+            u64_at(bytes, 8),
+            u64_at(bytes, 24),
+            u64_at(bytes, 32),
+            u64_at(bytes, 40),
+        ];
+
+        let mut at = FRAME_HEADER_SIZE;
+        let mut signals = vec![];
+        for _ in 0..num_signals {
+            let offset = u64_at(bytes, at + 12) as usize;
+            let length = u64_at(bytes, at + 20) as usize;
+            let name_len = u16_at(bytes, at + 28) as usize;
+            let name_start = at + FRAME_SIGNAL_ENTRY_SIZE;
+            assert_eq!(offset % FRAME_ALIGNMENT, 0);
+            assert!(offset >= data_offset);
+
+            let dtype = bytes[at + 1];
+            let samples = (0..length)
+                .map(|i| match dtype {
+                    0 => f64::from_le_bytes(bytes[offset + i * 8..][..8].try_into().unwrap()),
+                    _ => {
+                        f32::from_le_bytes(bytes[offset + i * 4..][..4].try_into().unwrap()) as f64
+                    }
+                })
+                .collect();
+            signals.push(DecodedSignal {
+                kind: bytes[at],
+                dtype,
+                polarization: u16_at(bytes, at + 2),
+                layer: u32_at(bytes, at + 4),
+                cell: u32_at(bytes, at + 8),
+                name: String::from_utf8(bytes[name_start..name_start + name_len].to_vec()).unwrap(),
+                samples,
+            });
+            at = name_start + name_len;
+        }
+        assert!(at <= data_offset);
+        (header, signals)
+    }
+
+    fn clock(index: usize) -> SignalDescriptor {
+        SignalDescriptor {
+            index: SignalIndex {
+                kind: SignalKind::Clock,
+                index,
+                subindex: None,
+            },
+            name: format!("Clock {}", index),
+            cell: None,
+        }
+    }
+
+    fn cell(index: usize, subindex: usize, name: &str) -> SignalDescriptor {
+        SignalDescriptor {
+            index: SignalIndex {
+                kind: SignalKind::Cell,
+                index,
+                subindex: Some(subindex),
+            },
+            name: name.to_string(),
+            cell: Some(QCACellIndex { layer: 1, cell: 7 }),
+        }
+    }
+
+    #[test]
+    fn round_trips_f64_frames() {
+        let samples: Vec<f64> = (0..10).map(|i| i as f64 * 0.25 - 1.0).collect();
+        let window = SampleWindow::new(Some(1), Some(8), Some(3), samples.len()).unwrap();
+        let mut frame = SimulationFrame::new(window, samples.len(), SampleType::F64);
+        frame.push_samples(clock(2), window.apply(&samples));
+        frame.push_view(cell(0, 1, "out"), &SignalView::from_samples(&samples));
+
+        let bytes = frame.encode();
+        assert_eq!(bytes.len() % FRAME_ALIGNMENT, 0);
+        let (header, signals) = decode(&bytes);
+        assert_eq!(header, [3, 1, 3, 10]);
+
+        let expected = vec![samples[1], samples[4], samples[7]];
+        assert_eq!(signals.len(), 2);
+        assert_eq!(
+            (signals[0].kind, signals[0].layer, signals[0].cell),
+            (SignalKind::Clock as u8, CLOCK_LAYER, 2)
+        );
+        assert_eq!(signals[0].name, "Clock 2");
+        assert_eq!(signals[0].samples, expected);
+        assert_eq!(
+            (signals[1].kind, signals[1].layer, signals[1].cell),
+            (SignalKind::Cell as u8, 1, 7)
+        );
+        assert_eq!((signals[1].polarization, signals[1].dtype), (1, 0));
+        assert_eq!(signals[1].name, "out");
+        assert_eq!(signals[1].samples, expected);
+    }
+
+    #[test]
+    fn round_trips_f32_frames() {
+        let samples = [0.5, -0.75, 1.0];
+        let window = SampleWindow::new(None, None, None, samples.len()).unwrap();
+        let mut frame = SimulationFrame::new(window, samples.len(), SampleType::F32);
+        frame.push_samples(cell(3, 0, "a"), samples.to_vec());
+
+        let (header, signals) = decode(&frame.encode());
+        assert_eq!(header, [3, 0, 1, 3]);
+        assert_eq!(signals[0].dtype, SampleType::F32 as u8);
+        assert_eq!(signals[0].samples, samples);
+    }
+
+    #[test]
+    fn encodes_empty_frames() {
+        let window = SampleWindow::new(Some(4), Some(4), None, 4).unwrap();
+        let mut frame = SimulationFrame::new(window, 4, SampleType::F64);
+        frame.push_samples(clock(0), vec![]);
+
+        let (header, signals) = decode(&frame.encode());
+        assert_eq!(header[0], 0);
+        assert!(signals[0].samples.is_empty());
+    }
+
+    #[test]
+    fn parses_sample_types() {
+        assert_eq!("f64".parse::<SampleType>(), Ok(SampleType::F64));
+        assert_eq!("f32".parse::<SampleType>(), Ok(SampleType::F32));
+        assert!("u8".parse::<SampleType>().is_err());
+    }
+}
@@ -0,0 +1,99 @@
+use crate::migration::{migrate_design, migrate_metadata};
+use crate::sim_format::{ArchiveEntry, DESIGN_ENTRY, METADATA_ENTRY};
+use qca_core::design::file::QCADesign;
+use qca_core::simulation::file::QCASimulationMetadata;
+use serde::Serialize;
+use serde_json::Value;
+use std::fs::File;
+use std::io::{BufReader, Read};
+
+#[derive(Serialize)]
+pub struct SimulationFileInfo {
+    design: QCADesign,
+    metadata: QCASimulationMetadata,
+    file_size: u64,
+    entries: Vec<ArchiveEntry>,
+}
+
+pub struct SimulationHeader {
+    pub design: QCADesign,
+    pub metadata: QCASimulationMetadata,
+    pub entries: Vec<ArchiveEntry>,
+}
+
+/// Reads the contents of the entry holding the design or the metadata.
+pub fn read_entry<R: Read>(entry: &mut tar::Entry<R>) -> Result<Vec<u8>, String> {
+    let mut contents = vec![];
+    entry
+        .read_to_end(&mut contents)
+        .map_err(|_err| "Failed to read simulation file")?;
+    Ok(contents)
+}
+
+/// Reads the design and metadata of a .qcs archive, migrated to the current
+/// version. Sample data entries are skipped over by seeking.
+pub fn read_simulation_header(filename: &str) -> Result<SimulationHeader, String> {
+    let file = File::open(filename).map_err(|_err| "Failed to open file")?;
+    let mut archive = tar::Archive::new(BufReader::new(file));
+
+    let mut design = None;
+    let mut metadata = None;
+    let mut entries = vec![];
+    for entry in archive
+        .entries_with_seek()
+        .map_err(|_err| "Failed to read simulation file")?
+    {
+        let mut entry = entry.map_err(|_err| "Failed to read simulation file")?;
+        let path = entry
+            .path()
+            .map(|path| path.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        entries.push(ArchiveEntry {
+            path: path.clone(),
+            offset: entry.raw_file_position(),
+            size: entry.size(),
+        });
+
+        // Files that could not be migrated on disk are migrated here
+        if path == DESIGN_ENTRY {
+            let mut design_json: Value = serde_json::from_slice(&read_entry(&mut entry)?)
+                .map_err(|_err| "Invalid simulation design")?;
+            migrate_design(&mut design_json)?;
+            design = Some(
+                serde_json::from_value::<QCADesign>(design_json)
+                    .map_err(|_err| "Invalid simulation design")?,
+            );
+        } else if path == METADATA_ENTRY {
+            let mut metadata_json: Value = serde_json::from_slice(&read_entry(&mut entry)?)
+                .map_err(|_err| "Invalid simulation metadata")?;
+            migrate_metadata(&mut metadata_json)?;
+            metadata = Some(
+                serde_json::from_value::<QCASimulationMetadata>(metadata_json)
+                    .map_err(|_err| "Invalid simulation metadata")?,
+            );
+        }
+    }
+
+    Ok(SimulationHeader {
+        design: design.ok_or("Simulation file has no design")?,
+        metadata: metadata.ok_or("Simulation file has no metadata")?,
+        entries,
+    })
+}
+
+/// Describes a .qcs file from its tar headers alone, for previews of
+/// recent files.
+#[tauri::command(async)]
+pub fn inspect_simulation_file(filename: String) -> Result<SimulationFileInfo, String> {
+    let file_size = std::fs::metadata(&filename)
+        .map_err(|_err| "Failed to open file")?
+        .len();
+    let header = read_simulation_header(&filename)?;
+
+    Ok(SimulationFileInfo {
+        design: header.design,
+        metadata: header.metadata,
+        file_size,
+        entries: header.entries,
+    })
+}
@@ -0,0 +1,529 @@
+use crate::migration::replace_file;
+use crate::signal::{
+    cell_name, polarization_count, stored_cell_index, SignalIndex, SignalKind, SignalSource,
+    SignalView, CLOCK_COUNT,
+};
+use crate::sim_cache::SimulationCache;
+use qca_core::design::file::{QCADesign, SimulationSettings};
+use qca_core::simulation::file::{
+    write_to_file, QCACellData, QCASimulationData, QCASimulationMetadata,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::State;
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+/// Clock values closer than this are continuous, whatever the step between
+/// samples.
+const CLOCK_TOLERANCE: f64 = 1e-9;
+
+#[derive(Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MergeMode {
+    /// Consecutive runs recording the same cells, appended along time.
+    Concatenate,
+    /// Runs of the same length recording different cells. Cells recorded
+    /// by several runs are taken from the first.
+    Union,
+}
+
+#[derive(Serialize)]
+pub struct MergeReport {
+    design_hash: String,
+    metadata: QCASimulationMetadata,
+}
+
+fn hash_bytes(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(FNV_PRIME)
+    })
+}
+
+/// FNV-1a hash of a JSON value with object keys in sorted order, so it does
+/// not depend on map iteration order.
+fn hash_value(hash: u64, value: &Value) -> u64 {
+    match value {
+        Value::Object(object) => {
+            let mut keys: Vec<&String> = object.keys().collect();
+            keys.sort();
+            let hash = keys.into_iter().fold(hash_bytes(hash, b"{"), |hash, key| {
+                hash_value(hash_bytes(hash, key.as_bytes()), &object[key.as_str()])
+            });
+            hash_bytes(hash, b"}")
+        }
+        Value::Array(values) => {
+            let hash = values.iter().fold(hash_bytes(hash, b"["), hash_value);
+            hash_bytes(hash, b"]")
+        }
+        value => hash_bytes(hash, value.to_string().as_bytes()),
+    }
+}
+
+/// Hash of a design's layout, its layers and cell architectures. Simulation
+/// settings are not included.
+pub fn design_hash(design: &QCADesign) -> Result<String, String> {
+    let layout = serde_json::to_value((&design.layers, &design.cell_architectures))
+        .map_err(|_err| "Failed to serialize design")?;
+    Ok(format!("{:016x}", hash_value(FNV_OFFSET, &layout)))
+}
+
+/// Explains why `other` cannot be merged into `first`, if it cannot. Runs
+/// must share the design and simulation model settings; runs merged by
+/// union also the clock generator settings, as they must be the same run.
+fn check_compatible(
+    first: &dyn SignalSource,
+    other: &dyn SignalSource,
+    filename: &str,
+    mode: MergeMode,
+) -> Result<(), String> {
+    let (design_first, design_other) = (first.design(), other.design());
+    let (hash_first, hash_other) = (design_hash(design_first)?, design_hash(design_other)?);
+    if hash_first != hash_other {
+        return Err(format!(
+            "{} was simulated from a different design (hash {} instead of {})",
+            filename, hash_other, hash_first
+        ));
+    }
+
+    let (settings_first, settings_other) = (
+        &design_first.simulation_settings,
+        &design_other.simulation_settings,
+    );
+    let model = &settings_first.selected_simulation_model_id;
+    if *model != settings_other.selected_simulation_model_id {
+        return Err(format!(
+            "{} was simulated with model {} instead of {}",
+            filename,
+            settings_other
+                .selected_simulation_model_id
+                .as_deref()
+                .unwrap_or("none"),
+            model.as_deref().unwrap_or("none")
+        ));
+    }
+    let model_settings = |settings: &SimulationSettings| {
+        model
+            .as_ref()
+            .and_then(|id| settings.simulation_model_settings.get(id))
+            .map(|settings| {
+                (
+                    settings.model_settings.clone(),
+                    settings.clock_generator_settings.clone(),
+                )
+            })
+    };
+    let (model_first, model_other) = (
+        model_settings(settings_first).unwrap_or_default(),
+        model_settings(settings_other).unwrap_or_default(),
+    );
+    if model_first.0 != model_other.0 {
+        return Err(format!(
+            "{} was simulated with different model settings",
+            filename
+        ));
+    }
+    if mode == MergeMode::Union && model_first.1 != model_other.1 {
+        return Err(format!(
+            "{} was simulated with different clock generator settings",
+            filename
+        ));
+    }
+
+    let (metadata_first, metadata_other) = (first.metadata(), other.metadata());
+    match mode {
+        MergeMode::Concatenate => {
+            let missing = metadata_first
+                .stored_cells
+                .iter()
+                .find(|cell| !metadata_other.stored_cells.contains(cell));
+            if let Some(cell) = missing {
+                return Err(format!(
+                    "{} does not record cell {}",
+                    filename,
+                    cell_name(design_first, cell)
+                ));
+            }
+            if metadata_other.stored_cells.len() != metadata_first.stored_cells.len() {
+                return Err(format!("{} records additional cells", filename));
+            }
+        }
+        MergeMode::Union => {
+            if metadata_other.num_samples != metadata_first.num_samples {
+                return Err(format!(
+                    "{} has {} samples instead of {}",
+                    filename, metadata_other.num_samples, metadata_first.num_samples
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn clock_view(simulation: &dyn SignalSource, clock: usize) -> Result<SignalView<'_>, String> {
+    simulation.signal_view(&SignalIndex {
+        kind: SignalKind::Clock,
+        index: clock,
+        subindex: None,
+    })
+}
+
+/// Explains why `next` does not continue `previous`, if it does not: it
+/// must have been started after `previous` finished, and its clocks must
+/// pick up where those of `previous` stop, without a larger step than any
+/// within `previous`.
+fn check_consecutive(
+    previous: &dyn SignalSource,
+    next: &dyn SignalSource,
+    previous_filename: &str,
+    filename: &str,
+) -> Result<(), String> {
+    let (metadata_previous, metadata_next) = (previous.metadata(), next.metadata());
+    if metadata_next.start_time < metadata_previous.start_time + metadata_previous.duration {
+        return Err(format!(
+            "{} was started before {} finished, so it does not continue it",
+            filename, previous_filename
+        ));
+    }
+
+    for clock in 0..CLOCK_COUNT {
+        let (view_previous, view_next) = (clock_view(previous, clock)?, clock_view(next, clock)?);
+        let (last, first) = match (
+            view_previous
+                .len()
+                .checked_sub(1)
+                .and_then(|i| view_previous.get(i)),
+            view_next.get(0),
+        ) {
+            (Some(last), Some(first)) => (last, first),
+            _ => continue,
+        };
+        let max_step = view_previous
+            .iter()
+            .zip(view_previous.iter().skip(1))
+            .map(|(a, b)| (b - a).abs())
+            .fold(0.0, f64::max);
+        if (first - last).abs() > max_step + CLOCK_TOLERANCE {
+            return Err(format!(
+                "{} does not continue the clocks of {}: clock {} jumps from {} to {}",
+                filename, previous_filename, clock, last, first
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// A stored cell's samples, interleaved per polarization as qca-core
+/// stores them.
+fn cell_samples(simulation: &dyn SignalSource, stored_index: usize) -> Result<Vec<f64>, String> {
+    let cell = &simulation.metadata().stored_cells[stored_index];
+    let views = (0..polarization_count(simulation.design(), cell)?)
+        .map(|polarization| {
+            simulation.signal_view(&SignalIndex {
+                kind: SignalKind::Cell,
+                index: stored_index,
+                subindex: Some(polarization),
+            })
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    Ok((0..simulation.metadata().num_samples)
+        .flat_map(|sample| views.iter().filter_map(move |view| view.get(sample)))
+        .collect())
+}
+
+fn merge(
+    simulations: &[&dyn SignalSource],
+    filenames: &[String],
+    mode: MergeMode,
+) -> Result<QCASimulationData, String> {
+    let (first, rest) = simulations.split_first().ok_or("No simulations to merge")?;
+    for (simulation, filename) in rest.iter().zip(&filenames[1..]) {
+        check_compatible(*first, *simulation, filename, mode)?;
+    }
+    if mode == MergeMode::Concatenate {
+        for (pair, names) in simulations.windows(2).zip(filenames.windows(2)) {
+            check_consecutive(pair[0], pair[1], &names[0], &names[1])?;
+        }
+    }
+
+    let mut clock_data: [Vec<f64>; CLOCK_COUNT] = Default::default();
+    for (clock, samples) in clock_data.iter_mut().enumerate() {
+        *samples = clock_view(*first, clock)?.to_vec();
+    }
+    let mut data = QCASimulationData {
+        metadata: first.metadata().clone(),
+        clock_data,
+        cells_data: (0..first.metadata().stored_cells.len())
+            .map(|i| {
+                Ok(QCACellData {
+                    data: cell_samples(*first, i)?,
+                })
+            })
+            .collect::<Result<_, String>>()?,
+    };
+    for simulation in rest {
+        let metadata = simulation.metadata();
+        match mode {
+            MergeMode::Concatenate => {
+                for (clock, samples) in data.clock_data.iter_mut().enumerate() {
+                    samples.extend(clock_view(*simulation, clock)?.iter());
+                }
+                // The runs may store their cells in a different order
+                for (i, cell) in data.metadata.stored_cells.iter().enumerate() {
+                    let j = stored_cell_index(metadata, cell)?;
+                    data.cells_data[i]
+                        .data
+                        .extend(cell_samples(*simulation, j)?);
+                }
+                data.metadata.num_samples += metadata.num_samples;
+                data.metadata.duration += metadata.duration;
+            }
+            MergeMode::Union => {
+                for (j, cell) in metadata.stored_cells.iter().enumerate() {
+                    if !data.metadata.stored_cells.contains(cell) {
+                        data.metadata.stored_cells.push(*cell);
+                        data.cells_data.push(QCACellData {
+                            data: cell_samples(*simulation, j)?,
+                        });
+                    }
+                }
+            }
+        }
+    }
+    Ok(data)
+}
+
+/// Merges simulations of the same design into a new .qcs file, in the
+/// given order. The merged file takes the design, settings and start time
+/// of the first simulation. It is written through a temporary file and
+/// may not replace one of the merged simulations.
+#[tauri::command(async)]
+pub fn merge_simulations(
+    cache: State<'_, SimulationCache>,
+    filenames: Vec<String>,
+    output_filename: String,
+    mode: MergeMode,
+) -> Result<MergeReport, String> {
+    if filenames.len() < 2 {
+        return Err("At least two simulations are needed to merge".into());
+    }
+    if let Ok(output) = std::fs::canonicalize(&output_filename) {
+        let input = filenames
+            .iter()
+            .find(|filename| std::fs::canonicalize(filename).ok().as_ref() == Some(&output));
+        if let Some(input) = input {
+            return Err(format!(
+                "The merged simulation cannot replace {}, which is being merged",
+                input
+            ));
+        }
+    }
+
+    let accesses = filenames
+        .iter()
+        .map(|filename| cache.access(filename))
+        .collect::<Result<Vec<_>, String>>()?;
+    let simulations: Vec<&dyn SignalSource> =
+        accesses.iter().map(|access| access.source()).collect();
+    let data = merge(&simulations, &filenames, mode)?;
+
+    let design = simulations[0].design();
+    replace_file(&output_filename, |file| {
+        write_to_file(file, design, &data).map_err(|_err| "Failed to write to file".into())
+    })?;
+
+    Ok(MergeReport {
+        design_hash: design_hash(design)?,
+        metadata: data.metadata,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim_cache::LoadedSimulation;
+    use qca_core::objects::cell::QCACellIndex;
+    use serde_json::json;
+
+    const A: QCACellIndex = QCACellIndex { layer: 0, cell: 0 };
+    const B: QCACellIndex = QCACellIndex { layer: 0, cell: 1 };
+
+    fn design(model: &str) -> QCADesign {
+        let cell = json!({
+            "position": [0.0, 0.0],
+            "rotation": 0.0,
+            "typ": "Normal",
+            "clock_phase_shift": 0.0,
+            "dot_probability_distribution": [],
+            "label": null,
+        });
+        serde_json::from_value(json!({
+            "qca_core_version": qca_core::QCA_CORE_VERSION,
+            "layers": [{
+                "name": "Main",
+                "visible": true,
+                "cell_architecture_id": "binary",
+                "cells": [cell, cell],
+                "z_position": 0.0,
+            }],
+            "cell_architectures": {
+                "binary": {
+                    "side_length": 18.0,
+                    "dot_diameter": 5.0,
+                    "dot_count": 4,
+                    "dot_positions": [],
+                    "dot_tunnels": [],
+                },
+            },
+            "simulation_settings": {
+                "selected_simulation_model_id": model,
+                "simulation_model_settings": {
+                    model: {
+                        "model_settings": { "temperature": 1.0 },
+                        "clock_generator_settings": { "amplitude": 1.0 },
+                    },
+                },
+            },
+        }))
+        .unwrap()
+    }
+
+    /// A run of `clock` samples on every clock, starting at `start` seconds
+    /// and taking a second, with each stored cell at a constant value.
+    fn run(
+        start: i64,
+        clock: &[f64],
+        cells: &[(QCACellIndex, f64)],
+        design: QCADesign,
+    ) -> LoadedSimulation {
+        let metadata = serde_json::from_value(json!({
+            "qca_core_version": qca_core::QCA_CORE_VERSION,
+            "start_time": chrono::DateTime::from_timestamp(start, 0).unwrap().to_rfc3339(),
+            "duration": { "seconds": 1, "nanoseconds": 0 },
+            "num_samples": clock.len(),
+            "stored_cells": cells.iter().map(|(cell, _)| *cell).collect::<Vec<_>>(),
+        }))
+        .unwrap();
+        LoadedSimulation {
+            design,
+            data: QCASimulationData {
+                metadata,
+                clock_data: [
+                    clock.to_vec(),
+                    clock.to_vec(),
+                    clock.to_vec(),
+                    clock.to_vec(),
+                ],
+                cells_data: cells
+                    .iter()
+                    .map(|(_, value)| QCACellData {
+                        data: vec![*value; clock.len()],
+                    })
+                    .collect(),
+            },
+        }
+    }
+
+    fn merged(
+        simulations: &[&LoadedSimulation],
+        mode: MergeMode,
+    ) -> Result<QCASimulationData, String> {
+        let sources: Vec<&dyn SignalSource> = simulations
+            .iter()
+            .map(|simulation| *simulation as &dyn SignalSource)
+            .collect();
+        let filenames: Vec<String> = (0..simulations.len())
+            .map(|i| format!("run{}.qcs", i))
+            .collect();
+        merge(&sources, &filenames, mode)
+    }
+
+    #[test]
+    fn concatenates_consecutive_runs() {
+        let first = run(0, &[0.0, 0.5, 1.0], &[(A, 1.0), (B, -1.0)], design("icha"));
+        // Stored in a different order
+        let second = run(10, &[0.5, 0.0], &[(B, -0.5), (A, 0.5)], design("icha"));
+
+        let data = merged(&[&first, &second], MergeMode::Concatenate).unwrap();
+        assert_eq!(data.metadata.num_samples, 5);
+        assert_eq!(data.metadata.duration.num_seconds(), 2);
+        assert_eq!(data.metadata.stored_cells, [A, B]);
+        assert_eq!(data.clock_data[3], [0.0, 0.5, 1.0, 0.5, 0.0]);
+        assert_eq!(data.cells_data[0].data, [1.0, 1.0, 1.0, 0.5, 0.5]);
+        assert_eq!(data.cells_data[1].data, [-1.0, -1.0, -1.0, -0.5, -0.5]);
+    }
+
+    #[test]
+    fn refuses_runs_that_are_not_consecutive() {
+        let first = run(0, &[0.0, 0.5, 1.0], &[(A, 1.0)], design("icha"));
+
+        let overlapping = run(0, &[0.5, 0.0], &[(A, 1.0)], design("icha"));
+        let err = merged(&[&first, &overlapping], MergeMode::Concatenate).unwrap_err();
+        assert_eq!(
+            err,
+            "run1.qcs was started before run0.qcs finished, so it does not continue it"
+        );
+
+        let restarted = run(10, &[0.0, 0.5], &[(A, 1.0)], design("icha"));
+        let err = merged(&[&first, &restarted], MergeMode::Concatenate).unwrap_err();
+        assert_eq!(
+            err,
+            "run1.qcs does not continue the clocks of run0.qcs: clock 0 jumps from 1 to 0"
+        );
+    }
+
+    #[test]
+    fn unites_cells_of_runs_of_the_same_length() {
+        let first = run(0, &[0.0, 1.0], &[(A, 1.0)], design("icha"));
+        // Cells recorded by both runs are taken from the first
+        let second = run(0, &[0.0, 1.0], &[(A, -1.0), (B, 0.5)], design("icha"));
+
+        let data = merged(&[&first, &second], MergeMode::Union).unwrap();
+        assert_eq!(data.metadata.num_samples, 2);
+        assert_eq!(data.metadata.stored_cells, [A, B]);
+        assert_eq!(data.cells_data[0].data, [1.0, 1.0]);
+        assert_eq!(data.cells_data[1].data, [0.5, 0.5]);
+        assert_eq!(data.clock_data[0], [0.0, 1.0]);
+    }
+
+    #[test]
+    fn refuses_incompatible_runs() {
+        let first = run(0, &[0.0, 1.0], &[(A, 1.0)], design("icha"));
+
+        let mut other_design = design("icha");
+        other_design.layers[0].cells.pop();
+        let other = run(10, &[1.0, 1.0], &[(A, 1.0)], other_design);
+        let err = merged(&[&first, &other], MergeMode::Concatenate).unwrap_err();
+        assert!(err.starts_with("run1.qcs was simulated from a different design"));
+
+        let other = run(10, &[1.0, 1.0], &[(A, 1.0)], design("bistable"));
+        let err = merged(&[&first, &other], MergeMode::Concatenate).unwrap_err();
+        assert_eq!(
+            err,
+            "run1.qcs was simulated with model bistable instead of icha"
+        );
+
+        let mut other_settings = design("icha");
+        other_settings
+            .simulation_settings
+            .simulation_model_settings
+            .get_mut("icha")
+            .unwrap()
+            .model_settings = json!({ "temperature": 2.0 });
+        let other = run(10, &[1.0, 1.0], &[(A, 1.0)], other_settings);
+        let err = merged(&[&first, &other], MergeMode::Concatenate).unwrap_err();
+        assert_eq!(err, "run1.qcs was simulated with different model settings");
+
+        let other = run(10, &[1.0, 1.0], &[(B, 1.0)], design("icha"));
+        let err = merged(&[&first, &other], MergeMode::Concatenate).unwrap_err();
+        assert_eq!(err, "run1.qcs does not record cell Cell 0-0");
+
+        let other = run(10, &[1.0, 1.0], &[(A, 1.0), (B, 1.0)], design("icha"));
+        let err = merged(&[&first, &other], MergeMode::Concatenate).unwrap_err();
+        assert_eq!(err, "run1.qcs records additional cells");
+
+        let other = run(0, &[0.0, 1.0, 0.0], &[(B, 1.0)], design("icha"));
+        let err = merged(&[&first, &other], MergeMode::Union).unwrap_err();
+        assert_eq!(err, "run1.qcs has 3 samples instead of 2");
+    }
+}
@@ -0,0 +1,339 @@
+use crate::signal::{SignalIndex, SignalKind, SignalSource, SignalView, CLOCK_COUNT};
+use crate::sim_cache::LoadedSimulation;
+use crate::sim_format::{sample_layout, SampleLayout, SimulationLayout};
+use crate::sim_header::read_simulation_header;
+use memmap2::Mmap;
+use qca_core::design::file::QCADesign;
+use qca_core::simulation::file::{QCACellData, QCASimulationData, QCASimulationMetadata};
+use std::fs::File;
+use std::time::SystemTime;
+
+/// A .qcs file mapped into memory. Samples are read from the mapping on
+/// access, so only the pages actually touched are loaded.
+pub struct MappedSimulation {
+    design: QCADesign,
+    metadata: QCASimulationMetadata,
+    file: File,
+    modified: SystemTime,
+    mmap: Mmap,
+    layout: SimulationLayout,
+}
+
+impl MappedSimulation {
+    /// Maps a simulation file. Fails if its sample data does not have the
+    /// layout described in `sim_format`.
+    pub fn open(filename: &str) -> Result<MappedSimulation, String> {
+        let header = read_simulation_header(filename)?;
+        let layout = sample_layout(&header.design, &header.metadata, &header.entries)?;
+
+        let file = File::open(filename).map_err(|_err| "Failed to open file")?;
+        let modified = file
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .map_err(|_err| "Failed to open file")?;
+        // SAFETY: the mapping is read only. The app writes simulation files
+        // through `replace_file`, which renames a new file over the old one
+        // and leaves the mapped file intact. Files changed by other programs
+        // are caught by `check_unchanged` before any view is handed out.
+        let mmap = unsafe { Mmap::map(&file) }.map_err(|_err| "Failed to map file")?;
+        let end = |layout: &SampleLayout| layout.offset + layout.span;
+        if layout
+            .clocks
+            .iter()
+            .chain(layout.cells.iter().flatten())
+            .any(|layout| end(layout) > mmap.len() as u64)
+        {
+            return Err("Simulation file is truncated".into());
+        }
+
+        Ok(MappedSimulation {
+            design: header.design,
+            metadata: header.metadata,
+            file,
+            modified,
+            mmap,
+            layout,
+        })
+    }
+
+    /// Fails if the mapped file was truncated or rewritten in place since it
+    /// was mapped, as reading a truncated mapping crashes the process.
+    fn check_unchanged(&self) -> Result<(), String> {
+        let metadata = self
+            .file
+            .metadata()
+            .map_err(|_err| "Failed to read simulation file")?;
+        let modified = metadata
+            .modified()
+            .map_err(|_err| "Failed to read simulation file")?;
+        if metadata.len() < self.mmap.len() as u64 || modified != self.modified {
+            return Err("Simulation file changed while it was open".into());
+        }
+        Ok(())
+    }
+
+    fn view(&self, layout: &SampleLayout) -> SignalView<'_> {
+        let start = layout.offset as usize;
+        SignalView::from_bytes(
+            &self.mmap[start..start + layout.span as usize],
+            layout.stride as usize / 8,
+            0,
+        )
+    }
+
+    /// Memory taken by the signals once loaded.
+    pub fn memory_size(&self) -> usize {
+        let signals =
+            self.layout.clocks.len() + self.layout.cells.iter().map(Vec::len).sum::<usize>();
+        signals * self.metadata.num_samples * std::mem::size_of::<f64>()
+    }
+
+    /// Copies every signal into memory.
+    pub fn load(&self) -> Result<LoadedSimulation, String> {
+        self.check_unchanged()?;
+        let mut clock_data: [Vec<f64>; CLOCK_COUNT] = Default::default();
+        for (clock, layout) in clock_data.iter_mut().zip(&self.layout.clocks) {
+            *clock = self.view(layout).to_vec();
+        }
+        let cells_data = self
+            .layout
+            .cells
+            .iter()
+            .map(|polarizations| {
+                let views: Vec<SignalView> = polarizations
+                    .iter()
+                    .map(|layout| self.view(layout))
+                    .collect();
+                // Cell data is interleaved per polarization
+                QCACellData {
+                    data: (0..self.metadata.num_samples)
+                        .flat_map(|sample| views.iter().filter_map(move |view| view.get(sample)))
+                        .collect(),
+                }
+            })
+            .collect();
+
+        Ok(LoadedSimulation {
+            design: self.design.clone(),
+            data: QCASimulationData {
+                metadata: self.metadata.clone(),
+                clock_data,
+                cells_data,
+            },
+        })
+    }
+}
+
+impl SignalSource for MappedSimulation {
+    fn design(&self) -> &QCADesign {
+        &self.design
+    }
+
+    fn metadata(&self) -> &QCASimulationMetadata {
+        &self.metadata
+    }
+
+    fn signal_view(&self, index: &SignalIndex) -> Result<SignalView<'_>, String> {
+        self.check_unchanged()?;
+        match index.kind {
+            SignalKind::Clock => self
+                .layout
+                .clocks
+                .get(index.index)
+                .map(|layout| self.view(layout))
+                .ok_or(format!("Clock {} does not exist", index.index)),
+            SignalKind::Cell => {
+                let cell = self
+                    .metadata
+                    .stored_cells
+                    .get(index.index)
+                    .ok_or(format!("Stored cell {} does not exist", index.index))?;
+                let polarization = index.subindex.unwrap_or(0);
+                self.layout.cells[index.index]
+                    .get(polarization)
+                    .map(|layout| self.view(layout))
+                    .ok_or(format!(
+                        "Cell {}-{} has no polarization {}",
+                        cell.layer, cell.cell, polarization
+                    ))
+            }
+            SignalKind::Derived => {
+                Err("Derived signals are evaluated from their expression".into())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use qca_core::objects::cell::QCACellIndex;
+    use qca_core::simulation::file::write_to_file;
+    use serde_json::json;
+
+    fn cell(typ: &str) -> serde_json::Value {
+        json!({
+            "position": [0.0, 0.0],
+            "rotation": 0.0,
+            "typ": typ,
+            "clock_phase_shift": 0.0,
+            "dot_probability_distribution": [],
+            "label": null,
+        })
+    }
+
+    fn architecture(dot_count: u8) -> serde_json::Value {
+        json!({
+            "side_length": 18.0,
+            "dot_diameter": 5.0,
+            "dot_count": dot_count,
+            "dot_positions": [],
+            "dot_tunnels": [],
+        })
+    }
+
+    /// A design with a 4 dot cell layer and an 8 dot cell layer.
+    fn design() -> QCADesign {
+        let layer = |architecture: &str, cells: Vec<serde_json::Value>| {
+            json!({
+                "name": architecture,
+                "visible": true,
+                "cell_architecture_id": architecture,
+                "cells": cells,
+                "z_position": 0.0,
+            })
+        };
+        serde_json::from_value(json!({
+            "qca_core_version": qca_core::QCA_CORE_VERSION,
+            "layers": [
+                layer("binary", vec![cell("Input"), cell("Normal"), cell("Output")]),
+                layer("ternary", vec![cell("Normal")]),
+            ],
+            "cell_architectures": {
+                "binary": architecture(4),
+                "ternary": architecture(8),
+            },
+            "simulation_settings": {
+                "selected_simulation_model_id": null,
+                "simulation_model_settings": {},
+            },
+        }))
+        .unwrap()
+    }
+
+    fn metadata(num_samples: usize, stored_cells: Vec<QCACellIndex>) -> QCASimulationMetadata {
+        serde_json::from_value(json!({
+            "qca_core_version": qca_core::QCA_CORE_VERSION,
+            "start_time": "2024-05-01T12:00:00+00:00",
+            "duration": { "seconds": 1, "nanoseconds": 0 },
+            "num_samples": num_samples,
+            "stored_cells": stored_cells,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn maps_files_written_by_qca_core() {
+        let num_samples = 1000;
+        let stored_cells = vec![
+            QCACellIndex { layer: 0, cell: 2 },
+            QCACellIndex { layer: 1, cell: 0 },
+            QCACellIndex { layer: 0, cell: 0 },
+        ];
+        let design = design();
+        let clock = |i: usize| -> Vec<f64> {
+            (0..num_samples)
+                .map(|j| ((j + 250 * i) as f64 / 100.0).sin())
+                .collect()
+        };
+        let data = QCASimulationData {
+            metadata: metadata(num_samples, stored_cells.clone()),
+            clock_data: [clock(0), clock(1), clock(2), clock(3)],
+            cells_data: vec![
+                QCACellData {
+                    data: (0..num_samples).map(|j| j as f64 / 1000.0).collect(),
+                },
+                QCACellData {
+                    data: (0..2 * num_samples).map(|j| -(j as f64)).collect(),
+                },
+                QCACellData {
+                    data: vec![0.5; num_samples],
+                },
+            ],
+        };
+
+        let path = std::env::temp_dir().join(format!("qca-forge-mmap-{}.qcs", std::process::id()));
+        let filename = path.to_str().unwrap();
+        write_to_file(File::create(&path).unwrap(), &design, &data).unwrap();
+        let mapped = MappedSimulation::open(filename);
+        let _ = std::fs::remove_file(&path);
+        let mapped = mapped.unwrap();
+
+        assert_eq!(mapped.metadata().num_samples, num_samples);
+        assert_eq!(mapped.metadata().stored_cells, stored_cells);
+        let view = |kind, index, subindex| {
+            mapped
+                .signal_view(&SignalIndex {
+                    kind,
+                    index,
+                    subindex,
+                })
+                .unwrap()
+                .to_vec()
+        };
+        for (i, clock) in data.clock_data.iter().enumerate() {
+            assert_eq!(&view(SignalKind::Clock, i, None), clock);
+        }
+        assert_eq!(view(SignalKind::Cell, 0, None), data.cells_data[0].data);
+        let ternary = &data.cells_data[1].data;
+        let polarization =
+            |p: usize| -> Vec<f64> { ternary.iter().skip(p).step_by(2).copied().collect() };
+        assert_eq!(view(SignalKind::Cell, 1, Some(0)), polarization(0));
+        assert_eq!(view(SignalKind::Cell, 1, Some(1)), polarization(1));
+        assert_eq!(view(SignalKind::Cell, 2, Some(0)), data.cells_data[2].data);
+
+        assert!(mapped
+            .signal_view(&SignalIndex {
+                kind: SignalKind::Cell,
+                index: 0,
+                subindex: Some(1),
+            })
+            .is_err());
+
+        let loaded = mapped.load().unwrap();
+        assert_eq!(loaded.data.clock_data, data.clock_data);
+        for (loaded, cell) in loaded.data.cells_data.iter().zip(&data.cells_data) {
+            assert_eq!(loaded.data, cell.data);
+        }
+    }
+
+    #[test]
+    fn refuses_files_truncated_while_mapped() {
+        let data = QCASimulationData {
+            metadata: metadata(100, vec![]),
+            clock_data: [
+                vec![0.0; 100],
+                vec![0.25; 100],
+                vec![0.5; 100],
+                vec![1.0; 100],
+            ],
+            cells_data: vec![],
+        };
+        let path =
+            std::env::temp_dir().join(format!("qca-forge-truncated-{}.qcs", std::process::id()));
+        write_to_file(File::create(&path).unwrap(), &design(), &data).unwrap();
+        let mapped = MappedSimulation::open(path.to_str().unwrap()).unwrap();
+        File::create(&path).unwrap().set_len(0).unwrap();
+
+        let view = mapped.signal_view(&SignalIndex {
+            kind: SignalKind::Clock,
+            index: 0,
+            subindex: None,
+        });
+        let loaded = mapped.load();
+        let _ = std::fs::remove_file(&path);
+
+        assert!(view.is_err());
+        assert!(loaded.is_err());
+    }
+}
@@ -1,10 +1,15 @@
-use std::fs::File;
+use crate::migration::replace_file;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 use qca_core::{
     design::file::QCADesign,
     simulation::{
-        file::write_to_file, icha::ICHAModel, model::SimulationModelTrait, run_simulation_async,
-        settings::OptionsList, SimulationProgress,
+        file::{write_to_file, QCASimulationData},
+        icha::ICHAModel,
+        model::SimulationModelTrait,
+        run_simulation_async,
+        settings::OptionsList,
+        SimulationProgress,
     },
 };
 use serde::Serialize;
@@ -52,14 +57,23 @@ pub fn create_sim_model(sim_model_id: String) -> Option<Box<dyn SimulationModelT
     None
 }
 
-#[tauri::command(async)]
-pub fn run_sim_model(app: AppHandle, qca_design: QCADesign) -> Result<String, String> {
+/// Simulates a design with its selected model, reporting progress in
+/// percent. Setting `stop` cancels the simulation.
+pub fn simulate_design(
+    qca_design: &QCADesign,
+    stop: &AtomicBool,
+    mut on_progress: impl FnMut(f32),
+) -> Result<QCASimulationData, String> {
     let sim_model_id = qca_design
         .simulation_settings
         .selected_simulation_model_id
         .clone()
-        .unwrap();
-    let sim_settings = &qca_design.simulation_settings.simulation_model_settings[&sim_model_id];
+        .ok_or("No simulation model is selected")?;
+    let sim_settings = qca_design
+        .simulation_settings
+        .simulation_model_settings
+        .get(&sim_model_id)
+        .ok_or("Simulation model has no settings")?;
     let sim_model_settings = sim_settings.model_settings.clone();
     let clock_generator_settings = sim_settings.clock_generator_settings.clone();
     let layers = qca_design.layers.clone();
@@ -74,27 +88,42 @@ pub fn run_sim_model(app: AppHandle, qca_design: QCADesign) -> Result<String, St
                 .deserialize_clock_generator_settings(&clock_generator_settings.to_string())
                 .map_err(|e| format!("Error parsing clock generator settings: {}", e))?;
 
-            let file = File::create("output.qcs").unwrap();
-
-            let (sim_handle, progress_rx, _) = run_simulation_async(model, layers, architectures);
+            let (sim_handle, progress_rx, sim_stop) =
+                run_simulation_async(model, layers, architectures);
 
             for progress in progress_rx {
+                if stop.load(Ordering::Relaxed) {
+                    sim_stop.store(true, Ordering::Relaxed);
+                }
                 match progress {
                     SimulationProgress::Running {
                         current_sample,
                         total_samples,
                     } => {
-                        let percent = (current_sample as f32 / total_samples as f32) * 100.0;
-                        app.emit("simulationProgress", percent).unwrap();
+                        on_progress((current_sample as f32 / total_samples as f32) * 100.0);
                     }
                     _ => {}
                 }
             }
 
-            let simulation_data = sim_handle.join().unwrap();
-            let _ = write_to_file(file, &qca_design, &simulation_data);
-            Ok("".into())
+            let data = sim_handle.join().map_err(|_err| "Simulation failed")?;
+            match stop.load(Ordering::Relaxed) {
+                true => Err("Simulation was cancelled".into()),
+                false => Ok(data),
+            }
         }
         None => Err("No model with such id exists".into()),
     }
 }
+
+#[tauri::command(async)]
+pub fn run_sim_model(app: AppHandle, qca_design: QCADesign) -> Result<String, String> {
+    let simulation_data = simulate_design(&qca_design, &AtomicBool::new(false), |percent| {
+        app.emit("simulationProgress", percent).unwrap();
+    })?;
+    replace_file("output.qcs", |file| {
+        write_to_file(file, &qca_design, &simulation_data)
+            .map_err(|_err| "Failed to write to file".into())
+    })?;
+    Ok("".into())
+}
@@ -0,0 +1,86 @@
+use crate::signal::{cell_signals, SampleWindow, SignalIndex, SignalKind, SignalView, CLOCK_COUNT};
+use crate::sim_cache::SimulationCache;
+use serde::Serialize;
+use std::collections::HashMap;
+use tauri::State;
+
+#[derive(Serialize)]
+pub struct PolarizationSnapshot {
+    start: usize,
+    end: usize,
+    clocks: Vec<f64>,
+    /// Polarization vector of every stored cell, keyed by its cell index.
+    cells: HashMap<String, Vec<f64>>,
+}
+
+fn window_mean(view: &SignalView, window: &SampleWindow) -> f64 {
+    let (sum, count) = window
+        .indices()
+        .filter_map(|sample| view.get(sample))
+        .fold((0.0, 0), |(sum, count), value| (sum + value, count + 1));
+    match count {
+        0 => f64::NAN,
+        _ => sum / count as f64,
+    }
+}
+
+/// The polarizations of all stored cells at `sample`, or averaged over
+/// `[sample, end)` when `end` is given, so the layout can be coloured
+/// without loading whole time series.
+#[tauri::command(async)]
+pub fn polarization_snapshot(
+    cache: State<'_, SimulationCache>,
+    filename: String,
+    sample: usize,
+    end: Option<usize>,
+) -> Result<PolarizationSnapshot, String> {
+    let simulation = cache.access(&filename)?;
+    let simulation = simulation.source();
+    let (design, metadata) = (simulation.design(), simulation.metadata());
+    let num_samples = metadata.num_samples;
+    if sample >= num_samples {
+        return Err(format!(
+            "Sample {} is past the last sample {}",
+            sample,
+            num_samples.saturating_sub(1)
+        ));
+    }
+    let window = SampleWindow::new(
+        Some(sample),
+        Some(end.unwrap_or(sample + 1).max(sample + 1)),
+        None,
+        num_samples,
+    )?;
+
+    let clocks = (0..CLOCK_COUNT)
+        .map(|clock| {
+            let index = SignalIndex {
+                kind: SignalKind::Clock,
+                index: clock,
+                subindex: None,
+            };
+            Ok(window_mean(&simulation.signal_view(&index)?, &window))
+        })
+        .collect::<Result<Vec<f64>, String>>()?;
+
+    let mut cells = HashMap::new();
+    for (i, cell) in metadata.stored_cells.iter().enumerate() {
+        let polarizations = cell_signals(design, i, cell)?
+            .iter()
+            .map(|signal| {
+                Ok(window_mean(
+                    &simulation.signal_view(&signal.index)?,
+                    &window,
+                ))
+            })
+            .collect::<Result<Vec<f64>, String>>()?;
+        cells.insert(format!("{}-{}", cell.layer, cell.cell), polarizations);
+    }
+
+    Ok(PolarizationSnapshot {
+        start: window.start,
+        end: window.end,
+        clocks,
+        cells,
+    })
+}
@@ -0,0 +1,240 @@
+use crate::clocking::{cell_clock_zone, hold_intervals};
+use crate::signal::{
+    cell_signals, stored_cell_index, SignalIndex, SignalKind, SignalView, CLOCK_COUNT,
+};
+use crate::sim_cache::SimulationCache;
+use crate::timing::simulation_timing;
+use qca_core::objects::cell::QCACellIndex;
+use serde::Serialize;
+use std::ops::Range;
+use tauri::State;
+
+const DEFAULT_SETTLING_TOLERANCE: f64 = 0.05;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Transition {
+    Rise,
+    Fall,
+    /// The polarization ended the cycle within the tolerance of the
+    /// previous cycle's value.
+    Hold,
+}
+
+/// Measurements of one clock cycle, which runs from the end of the previous
+/// hold phase to the end of this one.
+#[derive(Serialize)]
+pub struct CycleStatistics {
+    cycle: usize,
+    start: usize,
+    end: usize,
+    /// Polarization at the end of the hold phase.
+    hold_value: f64,
+    /// Change from the previous cycle's `hold_value`, or from the first
+    /// sample for the first cycle.
+    transition: Transition,
+    /// First sample where the polarization left the tolerance of the
+    /// previous value, if it switched.
+    onset: Option<usize>,
+    /// Samples from `onset` until the polarization stays within the
+    /// tolerance of `hold_value`.
+    settling_samples: usize,
+    /// `settling_samples` in seconds, if the clock frequency is known.
+    settling_time: Option<f64>,
+    /// How far the polarization went past `hold_value` in the direction of
+    /// the transition, relative to its magnitude.
+    overshoot: f64,
+}
+
+#[derive(Serialize)]
+pub struct SignalStatistics {
+    index: SignalIndex,
+    name: String,
+    min: f64,
+    max: f64,
+    mean: f64,
+    rms: f64,
+    final_value: f64,
+    cycles: Vec<CycleStatistics>,
+}
+
+fn cycle_statistics(
+    view: &SignalView,
+    holds: &[Range<usize>],
+    tolerance: f64,
+    sample_period: Option<f64>,
+) -> Vec<CycleStatistics> {
+    let mut cycles = vec![];
+    let mut start = 0;
+    let mut previous = match view.get(0) {
+        Some(value) => value,
+        None => return cycles,
+    };
+    for (cycle, hold) in holds.iter().enumerate() {
+        let end = hold.end;
+        let samples: Vec<f64> = (start..end).filter_map(|i| view.get(i)).collect();
+        let hold_value = match samples.last() {
+            Some(value) => *value,
+            None => break,
+        };
+
+        let transition = if (hold_value - previous).abs() <= tolerance {
+            Transition::Hold
+        } else if hold_value > previous {
+            Transition::Rise
+        } else {
+            Transition::Fall
+        };
+        // Switching starts when the polarization leaves the previous value
+        let onset = samples
+            .iter()
+            .position(|value| (value - previous).abs() > tolerance);
+        let settling_samples = match onset {
+            Some(onset) => samples
+                .iter()
+                .rposition(|value| (value - hold_value).abs() > tolerance)
+                .map_or(0, |last| (last + 1).saturating_sub(onset)),
+            None => 0,
+        };
+
+        let switching = &samples[onset.unwrap_or(samples.len())..];
+        let overshoot = match transition {
+            Transition::Rise => switching.iter().copied().fold(hold_value, f64::max) - hold_value,
+            Transition::Fall => hold_value - switching.iter().copied().fold(hold_value, f64::min),
+            Transition::Hold => 0.0,
+        };
+
+        cycles.push(CycleStatistics {
+            cycle,
+            start,
+            end,
+            hold_value,
+            transition,
+            onset: onset.map(|onset| start + onset),
+            settling_samples,
+            settling_time: sample_period.map(|period| settling_samples as f64 * period),
+            overshoot: match hold_value.abs() > 0.0 {
+                true => overshoot / hold_value.abs(),
+                false => 0.0,
+            },
+        });
+        previous = hold_value;
+        start = end;
+    }
+    cycles
+}
+
+/// Summary statistics of every polarization of the given cells (all stored
+/// cells by default), with per clock cycle settling measurements.
+#[tauri::command(async)]
+pub fn signal_statistics(
+    cache: State<'_, SimulationCache>,
+    filename: String,
+    cells: Option<Vec<QCACellIndex>>,
+    clock_threshold: f64,
+    tolerance: Option<f64>,
+) -> Result<Vec<SignalStatistics>, String> {
+    let access = cache.access(&filename)?;
+    let simulation = access.source();
+    let (design, metadata) = (simulation.design(), simulation.metadata());
+    let tolerance = tolerance.unwrap_or(DEFAULT_SETTLING_TOLERANCE);
+    // Settling times need the clock timing, settling samples do not
+    let sample_period = simulation_timing(design, metadata.num_samples)
+        .map(|timing| timing.sample_period())
+        .ok();
+
+    let holds = (0..CLOCK_COUNT)
+        .map(|clock| {
+            let index = SignalIndex {
+                kind: SignalKind::Clock,
+                index: clock,
+                subindex: None,
+            };
+            Ok(hold_intervals(
+                simulation.signal_view(&index)?.iter(),
+                clock_threshold,
+            ))
+        })
+        .collect::<Result<Vec<Vec<Range<usize>>>, String>>()?;
+
+    let cells = cells.unwrap_or(metadata.stored_cells.clone());
+    let mut statistics = vec![];
+    for cell in &cells {
+        let stored_index = stored_cell_index(metadata, cell)?;
+        let cell_holds = &holds[cell_clock_zone(design, cell)?];
+
+        for signal in cell_signals(design, stored_index, cell)? {
+            let view = simulation.signal_view(&signal.index)?;
+            let (mut min, mut max) = (f64::INFINITY, f64::NEG_INFINITY);
+            let (mut sum, mut square_sum, mut count) = (0.0, 0.0, 0);
+            let mut final_value = f64::NAN;
+            for value in view.iter() {
+                min = min.min(value);
+                max = max.max(value);
+                sum += value;
+                square_sum += value * value;
+                count += 1;
+                final_value = value;
+            }
+            if count == 0 {
+                return Err("Simulation has no samples".into());
+            }
+
+            statistics.push(SignalStatistics {
+                index: signal.index,
+                name: signal.name,
+                min,
+                max,
+                mean: sum / count as f64,
+                rms: (square_sum / count as f64).sqrt(),
+                final_value,
+                cycles: cycle_statistics(&view, cell_holds, tolerance, sample_period),
+            });
+        }
+    }
+
+    Ok(statistics)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transitions_compare_against_the_previous_cycle() {
+        // Stays positive but drops from 0.9 to 0.2, then rises to 0.8
+        let samples = [0.9, 0.9, 0.9, 0.5, 0.2, 0.2, 0.5, 0.8, 0.8, 0.8];
+        let view = SignalView::from_samples(&samples);
+        let holds = [2..3, 5..6, 9..10];
+        let cycles = cycle_statistics(&view, &holds, 0.05, None);
+
+        let transitions: Vec<Transition> = cycles.iter().map(|cycle| cycle.transition).collect();
+        assert_eq!(
+            transitions,
+            [Transition::Hold, Transition::Fall, Transition::Rise]
+        );
+        assert_eq!(cycles[1].hold_value, 0.2);
+    }
+
+    #[test]
+    fn settling_is_measured_from_the_switch_onset() {
+        // The cycle starts at sample 2, the cell holds -1 until sample 5,
+        // then switches and overshoots before settling at 1 on sample 8
+        let samples = [-1.0, -1.0, -1.0, -1.0, -1.0, 0.2, 1.2, 0.9, 1.0, 1.0, 1.0];
+        let view = SignalView::from_samples(&samples);
+        let holds = [0..2, 9..11];
+        let cycles = cycle_statistics(&view, &holds, 0.05, Some(0.5));
+
+        let cycle = &cycles[1];
+        assert_eq!(cycle.transition, Transition::Rise);
+        assert_eq!(cycle.onset, Some(5));
+        assert_eq!(cycle.settling_samples, 3);
+        assert_eq!(cycle.settling_time, Some(1.5));
+        assert!((cycle.overshoot - 0.2).abs() < 1e-9);
+
+        // A cycle that does not switch settles immediately
+        assert_eq!(cycles[0].onset, None);
+        assert_eq!(cycles[0].settling_samples, 0);
+        assert_eq!(cycles[0].overshoot, 0.0);
+    }
+}
@@ -0,0 +1,72 @@
+use crate::digital::DigitizeOptions;
+use serde::{Deserialize, Serialize};
+
+/// A ternary state and the `(P1, P2)` polarization pair it is read from.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TernaryLevel {
+    pub state: i32,
+    pub polarization: [f64; 2],
+}
+
+/// How the polarization pair of a tri-state (8 dot) cell maps to the
+/// ternary states −1, 0 and +1.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TernaryOptions {
+    /// Reference polarization pairs. A state may appear several times.
+    pub levels: Vec<TernaryLevel>,
+    /// Largest distance from a reference pair that is still read as its
+    /// state.
+    pub tolerance: f64,
+}
+
+impl Default for TernaryOptions {
+    /// ±1 along the first polarization, 0 along either end of the second.
+    fn default() -> Self {
+        let level = |state, p1, p2| TernaryLevel {
+            state,
+            polarization: [p1, p2],
+        };
+        TernaryOptions {
+            levels: vec![
+                level(1, 1.0, 0.0),
+                level(-1, -1.0, 0.0),
+                level(0, 0.0, 1.0),
+                level(0, 0.0, -1.0),
+            ],
+            tolerance: 0.5,
+        }
+    }
+}
+
+/// The state whose reference pair is nearest to `(p1, p2)`, if within the
+/// tolerance.
+pub fn ternary_state(p1: f64, p2: f64, options: &TernaryOptions) -> Option<i32> {
+    options
+        .levels
+        .iter()
+        .map(|level| {
+            let d1 = p1 - level.polarization[0];
+            let d2 = p2 - level.polarization[1];
+            (level.state, (d1 * d1 + d2 * d2).sqrt())
+        })
+        .filter(|(_, distance)| *distance <= options.tolerance)
+        .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal))
+        .map(|(state, _)| state)
+}
+
+/// Reads a ternary state with the thresholds of a binary truth table: the
+/// magnitude of the polarization pair must be above `logical_threshold` and
+/// reach `value_threshold` before it is read as a state.
+pub fn digitize_ternary(
+    p1: f64,
+    p2: f64,
+    thresholds: &DigitizeOptions,
+    options: &TernaryOptions,
+) -> Option<i32> {
+    let magnitude = p1.hypot(p2);
+    if magnitude <= thresholds.logical_threshold || magnitude < thresholds.value_threshold {
+        return None;
+    }
+    ternary_state(p1, p2, options)
+}
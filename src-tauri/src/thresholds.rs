@@ -0,0 +1,348 @@
+use crate::signal::{cell_signals, SignalIndex, SignalKind, SignalView, CLOCK_COUNT};
+use crate::sim_cache::SimulationCache;
+use qca_core::objects::cell::QCACellIndex;
+use serde::Serialize;
+use tauri::State;
+
+const DEFAULT_HISTOGRAM_BINS: usize = 100;
+
+/// A bin belongs to the hold plateau while it holds more than this many
+/// times the mean bin count, the density of evenly spread clock values.
+const PLATEAU_DENSITY: f64 = 2.0;
+
+#[derive(Serialize)]
+pub struct Histogram {
+    min: f64,
+    max: f64,
+    bin_width: f64,
+    counts: Vec<usize>,
+    /// Values the suggested threshold is taken from, in ascending order.
+    separations: Vec<f64>,
+}
+
+impl Histogram {
+    fn new(values: impl Iterator<Item = f64>, min: f64, max: f64, bins: usize) -> Histogram {
+        let bin_width = match max > min {
+            true => (max - min) / bins as f64,
+            false => 1.0,
+        };
+        let mut counts = vec![0; bins];
+        for value in values {
+            if value.is_nan() {
+                continue;
+            }
+            let bin = ((value - min) / bin_width).max(0.0) as usize;
+            counts[bin.min(bins - 1)] += 1;
+        }
+
+        Histogram {
+            min,
+            max,
+            bin_width,
+            counts,
+            separations: vec![],
+        }
+    }
+
+    fn bin_center(&self, bin: usize) -> f64 {
+        self.min + (bin as f64 + 0.5) * self.bin_width
+    }
+
+    /// The value where bin `edge` starts.
+    fn edge_value(&self, edge: usize) -> f64 {
+        self.min + edge as f64 * self.bin_width
+    }
+
+    /// Splits the histogram into `splits + 1` classes so the variance
+    /// between the classes is maximal (Otsu's method) and returns the
+    /// values separating them.
+    fn otsu(&self, splits: usize) -> Option<Vec<f64>> {
+        // Prefix sums of counts and of counts times bin centers, so the
+        // score of any class is found in constant time
+        let mut weights = vec![0.0];
+        let mut sums = vec![0.0];
+        for (bin, count) in self.counts.iter().enumerate() {
+            weights.push(weights[bin] + *count as f64);
+            sums.push(sums[bin] + *count as f64 * self.bin_center(bin));
+        }
+        let score = |start: usize, end: usize| {
+            let weight = weights[end] - weights[start];
+            let sum = sums[end] - sums[start];
+            (weight > 0.0).then(|| sum * sum / weight)
+        };
+
+        // Maximizing the between-class variance is maximizing the sum of
+        // weight * mean^2 over the classes
+        fn search(
+            bins: usize,
+            start: usize,
+            splits: usize,
+            score: &dyn Fn(usize, usize) -> Option<f64>,
+        ) -> Option<(f64, Vec<usize>)> {
+            if splits == 0 {
+                return score(start, bins).map(|score| (score, vec![]));
+            }
+            let mut best: Option<(f64, Vec<usize>)> = None;
+            for edge in start + 1..bins {
+                let class = match score(start, edge) {
+                    Some(class) => class,
+                    None => continue,
+                };
+                if let Some((rest, mut edges)) = search(bins, edge, splits - 1, score) {
+                    if best.as_ref().map_or(true, |(best, _)| class + rest > *best) {
+                        edges.insert(0, edge);
+                        best = Some((class + rest, edges));
+                    }
+                }
+            }
+            best
+        }
+
+        search(self.counts.len(), 0, splits, &score).map(|(_, edges)| {
+            edges
+                .into_iter()
+                .map(|edge| self.edge_value(edge))
+                .collect()
+        })
+    }
+
+    /// The end of the run of dense bins at the start of the histogram, or
+    /// `None` if the first bin is not denser than the rest.
+    fn plateau_end(&self) -> Option<f64> {
+        let mean = self.counts.iter().sum::<usize>() as f64 / self.counts.len() as f64;
+        let dense = |count: usize| count as f64 > PLATEAU_DENSITY * mean;
+
+        let end = self
+            .counts
+            .iter()
+            .position(|count| !dense(*count))
+            .unwrap_or(self.counts.len());
+        (end > 0).then(|| self.edge_value(end))
+    }
+}
+
+#[derive(Serialize)]
+pub struct ThresholdSuggestion {
+    clock_threshold: Option<f64>,
+    logical_threshold: Option<f64>,
+    value_threshold: Option<f64>,
+    /// Histogram of every clock's distance below its maximum, relative to
+    /// its amplitude, which `clock_threshold` is taken from.
+    hold_histogram: Histogram,
+    /// Histogram of polarizations, which `logical_threshold` is taken from.
+    polarization_histogram: Histogram,
+    /// Histogram of polarization magnitudes, which `value_threshold` is
+    /// taken from.
+    magnitude_histogram: Histogram,
+}
+
+/// Suggests each threshold from the quantity it is compared against.
+///
+/// Clocks hold while within `clock_threshold` of their maximum, so it is
+/// the width of the plateau at the top of the clocks. Polarizations within
+/// `logical_threshold` of zero are neither level, so it is the half-width
+/// of the middle class when splitting polarizations into a negative, a
+/// switching and a positive class. A polarization is settled once its
+/// magnitude reaches `value_threshold`, so it separates the settled and
+/// unsettled magnitudes.
+pub fn suggest(
+    clocks: &[SignalView],
+    polarizations: &[SignalView],
+    bins: usize,
+) -> ThresholdSuggestion {
+    let bins = bins.max(3);
+
+    let distances = clocks.iter().flat_map(|clock| {
+        let (min, max) = clock
+            .iter()
+            .filter(|value| !value.is_nan())
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), value| {
+                (min.min(value), max.max(value))
+            });
+        // Flat clocks never hold
+        let amplitude = max - min;
+        clock
+            .iter()
+            .filter(move |_| amplitude > 0.0)
+            .map(move |value| (max - value) / amplitude)
+    });
+    let mut hold_histogram = Histogram::new(distances, 0.0, 1.0, bins);
+    let clock_threshold = match hold_histogram.counts.iter().any(|count| *count > 0) {
+        true => hold_histogram.plateau_end(),
+        false => None,
+    };
+    hold_histogram.separations = clock_threshold.into_iter().collect();
+
+    let values = || polarizations.iter().flat_map(|view| view.iter());
+
+    let mut polarization_histogram = Histogram::new(values(), -1.0, 1.0, bins);
+    let switching = polarization_histogram
+        .otsu(2)
+        .filter(|separations| separations[0] < 0.0 && separations[1] > 0.0);
+    // The band is symmetric, so it ends at the nearer side of the
+    // switching class to keep every level sample out of it
+    let logical_threshold = switching
+        .as_ref()
+        .map(|separations| separations[1].min(-separations[0]));
+    polarization_histogram.separations = switching.unwrap_or_default();
+
+    let mut magnitude_histogram = Histogram::new(values().map(f64::abs), 0.0, 1.0, bins);
+    magnitude_histogram.separations = magnitude_histogram.otsu(1).unwrap_or_default();
+    let value_threshold = magnitude_histogram.separations.first().copied();
+
+    ThresholdSuggestion {
+        clock_threshold,
+        logical_threshold,
+        value_threshold,
+        hold_histogram,
+        polarization_histogram,
+        magnitude_histogram,
+    }
+}
+
+/// Suggests truth table thresholds from the value distributions of a run.
+#[tauri::command(async)]
+pub fn suggest_thresholds(
+    cache: State<'_, SimulationCache>,
+    filename: String,
+    cells: Option<Vec<QCACellIndex>>,
+    bins: Option<usize>,
+) -> Result<ThresholdSuggestion, String> {
+    let access = cache.access(&filename)?;
+    let simulation = access.source();
+    let (design, metadata) = (simulation.design(), simulation.metadata());
+
+    let clocks = (0..CLOCK_COUNT)
+        .map(|clock| {
+            simulation.signal_view(&SignalIndex {
+                kind: SignalKind::Clock,
+                index: clock,
+                subindex: None,
+            })
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    if clocks.iter().all(|clock| clock.is_empty()) {
+        return Err("Simulation has no clock samples".into());
+    }
+
+    let cells = cells.unwrap_or(metadata.stored_cells.clone());
+    let mut polarizations = vec![];
+    for cell in &cells {
+        let stored_index = metadata
+            .stored_cells
+            .iter()
+            .position(|stored| stored == cell)
+            .ok_or(format!("Cell {}-{} is not stored", cell.layer, cell.cell))?;
+        for signal in cell_signals(design, stored_index, cell)? {
+            polarizations.push(simulation.signal_view(&signal.index)?);
+        }
+    }
+
+    Ok(suggest(
+        &clocks,
+        &polarizations,
+        bins.unwrap_or(DEFAULT_HISTOGRAM_BINS),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clocking::hold_intervals;
+    use crate::digital::{digitize, DigitizeOptions, LogicValue};
+
+    /// A trapezoid clock: `hold` samples at 1 with a small ripple, a ramp
+    /// down, `hold` samples at -1 and a ramp up, repeated `cycles` times.
+    fn trapezoid(hold: usize, ramp: usize, cycles: usize) -> Vec<f64> {
+        let mut clock = vec![];
+        for _ in 0..cycles {
+            clock.extend((0..hold).map(|i| 1.0 - 0.002 * (i % 3) as f64));
+            clock.extend((0..ramp).map(|i| 1.0 - 2.0 * (i + 1) as f64 / (ramp + 1) as f64));
+            clock.extend(vec![-1.0; hold]);
+            clock.extend((0..ramp).map(|i| -1.0 + 2.0 * (i + 1) as f64 / (ramp + 1) as f64));
+        }
+        clock
+    }
+
+    /// A cell switching between -0.95 and 0.8 with linear transitions of
+    /// `ramp` samples.
+    fn polarization(level: usize, ramp: usize, cycles: usize) -> Vec<f64> {
+        let (low, high) = (-0.95, 0.8);
+        let mut cell = vec![];
+        for _ in 0..cycles {
+            cell.extend(vec![low; level]);
+            cell.extend((0..ramp).map(|i| low + (high - low) * (i + 1) as f64 / (ramp + 1) as f64));
+            cell.extend(vec![high; level]);
+            cell.extend(
+                (0..ramp).map(|i| high + (low - high) * (i + 1) as f64 / (ramp + 1) as f64),
+            );
+        }
+        cell
+    }
+
+    #[test]
+    fn clock_threshold_covers_the_hold_plateau() {
+        let clock = trapezoid(30, 40, 4);
+        let suggestion = suggest(&[SignalView::from_samples(&clock)], &[], 100);
+
+        let threshold = suggestion.clock_threshold.unwrap();
+        // Wide enough for the ripple, narrow enough to leave the ramps out
+        assert!(threshold > 0.001 && threshold < 0.05, "{}", threshold);
+        let holds = hold_intervals(&clock, threshold);
+        assert_eq!(holds.len(), 4);
+        assert!(holds.iter().all(|hold| hold.len() == 30), "{:?}", holds);
+    }
+
+    #[test]
+    fn clock_threshold_ignores_flat_clocks() {
+        let flat = vec![0.5; 100];
+        let suggestion = suggest(&[SignalView::from_samples(&flat)], &[], 100);
+        assert_eq!(suggestion.clock_threshold, None);
+    }
+
+    #[test]
+    fn thresholds_separate_levels_from_transitions() {
+        let cell = polarization(50, 20, 4);
+        let suggestion = suggest(&[], &[SignalView::from_samples(&cell)], 100);
+
+        let logical = suggestion.logical_threshold.unwrap();
+        let value = suggestion.value_threshold.unwrap();
+        // Both stay below the weaker level and above the middle of the
+        // transitions
+        assert!(logical > 0.1 && logical < 0.8, "{}", logical);
+        assert!(value > 0.1 && value < 0.8, "{}", value);
+
+        let options = DigitizeOptions {
+            logical_threshold: logical,
+            value_threshold: value,
+            hysteresis: 0.0,
+        };
+        let level = |p: f64| digitize(p, LogicValue::Unknown, &options);
+        assert_eq!(level(-0.95), LogicValue::Zero);
+        assert_eq!(level(0.8), LogicValue::One);
+        assert_eq!(level(0.0), LogicValue::Unknown);
+    }
+
+    #[test]
+    fn otsu_finds_the_valleys_of_three_modes() {
+        let values: Vec<f64> = [-0.8, 0.0, 0.8]
+            .iter()
+            .flat_map(|mode| (0..50).map(move |i| mode + (i % 5) as f64 * 0.01))
+            .collect();
+        let histogram = Histogram::new(values.into_iter(), -1.0, 1.0, 100);
+        let separations = histogram.otsu(2).unwrap();
+        assert!(
+            separations[0] > -0.75 && separations[0] <= 0.0,
+            "{:?}",
+            separations
+        );
+        assert!(
+            separations[1] > 0.05 && separations[1] <= 0.8,
+            "{:?}",
+            separations
+        );
+
+        let empty = Histogram::new(std::iter::empty(), 0.0, 1.0, 10);
+        assert_eq!(empty.otsu(1), None);
+    }
+}
@@ -0,0 +1,59 @@
+use qca_core::design::file::QCADesign;
+
+/// Clock generator settings the sample timing is derived from.
+const NUM_CYCLES_SETTING: &str = "num_cycles";
+const FREQUENCY_SETTING: &str = "frequency";
+
+/// Sample timing derived from the clock generator settings of the model a
+/// design was simulated with.
+#[derive(Clone, Copy, Debug)]
+pub struct SimulationTiming {
+    pub samples_per_cycle: f64,
+    /// Length of one clock cycle in seconds.
+    pub cycle_period: f64,
+}
+
+impl SimulationTiming {
+    pub fn sample_period(&self) -> f64 {
+        self.cycle_period / self.samples_per_cycle
+    }
+
+    pub fn sample_time(&self, sample: usize) -> f64 {
+        sample as f64 * self.sample_period()
+    }
+}
+
+fn clock_generator_setting(design: &QCADesign, name: &str) -> Result<f64, String> {
+    let model_id = design
+        .simulation_settings
+        .selected_simulation_model_id
+        .as_ref()
+        .ok_or("The simulation has no selected model to take its timing from")?;
+    design
+        .simulation_settings
+        .simulation_model_settings
+        .get(model_id)
+        .and_then(|settings| settings.clock_generator_settings.get(name))
+        .and_then(|value| value.as_f64())
+        .filter(|value| *value > 0.0)
+        .ok_or(format!(
+            "The clock generator of model '{}' has no positive '{}' setting",
+            model_id, name
+        ))
+}
+
+/// Fails if the clock generator settings do not give the number of clock
+/// cycles and the clock frequency, as the simulation does not store its
+/// sample times.
+pub fn simulation_timing(
+    design: &QCADesign,
+    num_samples: usize,
+) -> Result<SimulationTiming, String> {
+    let num_cycles = clock_generator_setting(design, NUM_CYCLES_SETTING)?;
+    let frequency = clock_generator_setting(design, FREQUENCY_SETTING)?;
+
+    Ok(SimulationTiming {
+        samples_per_cycle: num_samples as f64 / num_cycles,
+        cycle_period: 1.0 / frequency,
+    })
+}
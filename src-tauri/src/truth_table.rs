@@ -0,0 +1,424 @@
+use crate::clocking::{cell_clock_zone, clock_holds, row_sample};
+use crate::digital::{digitize, DigitizeOptions, LogicValue};
+use crate::signal::{
+    cell_name, polarization_count, stored_cell_index, SignalIndex, SignalKind, SignalSource,
+    SignalView,
+};
+use crate::signal_expr::{DerivedSignal, SignalExpr};
+use crate::sim_cache::SimulationCache;
+use crate::ternary::{digitize_ternary, TernaryOptions};
+use crate::verification::{parse_expectations, verify_table, OutputExpectation};
+use qca_core::design::file::QCADesign;
+use qca_core::objects::cell::{CellType, QCACellIndex};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Write;
+use std::ops::Range;
+use std::str::FromStr;
+use tauri::State;
+
+/// The arguments of `calculate_truth_table`, for commands that build on it.
+#[derive(Clone, Deserialize)]
+pub struct TruthTableSettings {
+    pub cells: Vec<QCACellIndex>,
+    #[serde(default)]
+    pub cell_clock_delay: HashMap<String, usize>,
+    pub clock_threshold: f64,
+    pub logical_threshold: f64,
+    pub value_threshold: f64,
+    /// Reads tri-state cells as ternary values instead of binary ones.
+    #[serde(default)]
+    pub ternary: Option<TernaryOptions>,
+    /// Derived signals added as output columns.
+    #[serde(default)]
+    pub derived: Vec<DerivedSignal>,
+}
+
+impl TruthTableSettings {
+    pub fn digitize_options(&self) -> DigitizeOptions {
+        DigitizeOptions {
+            logical_threshold: self.logical_threshold,
+            value_threshold: self.value_threshold,
+            hysteresis: 0.0,
+        }
+    }
+}
+
+#[derive(Clone, Serialize)]
+pub struct TruthTableColumn {
+    /// The column's cell, `None` for derived signals.
+    pub cell: Option<QCACellIndex>,
+    pub name: String,
+    pub is_output: bool,
+    pub clock_zone: usize,
+    pub clock_delay: usize,
+    pub values: Vec<Option<f64>>,
+}
+
+/// A truth table with its columns ordered inputs first, then outputs.
+#[derive(Clone, Serialize)]
+pub struct TruthTableData {
+    pub columns: Vec<TruthTableColumn>,
+    pub rows: usize,
+}
+
+impl TruthTableData {
+    pub fn inputs(&self) -> impl Iterator<Item = &TruthTableColumn> {
+        self.columns.iter().filter(|column| !column.is_output)
+    }
+
+    pub fn outputs(&self) -> impl Iterator<Item = &TruthTableColumn> {
+        self.columns.iter().filter(|column| column.is_output)
+    }
+}
+
+pub fn parse_cell_clock_delay(
+    cell_clock_delay: &HashMap<String, usize>,
+) -> Result<HashMap<QCACellIndex, usize>, String> {
+    cell_clock_delay
+        .iter()
+        .map(|(k, v)| {
+            let cell_index =
+                QCACellIndex::from_str(k).map_err(|_err| format!("Invalid cell index '{}'", k))?;
+            Ok((cell_index, *v))
+        })
+        .collect()
+}
+
+pub fn is_output_cell(design: &QCADesign, cell: &QCACellIndex) -> bool {
+    design
+        .layers
+        .get(cell.layer)
+        .and_then(|layer| layer.cells.get(cell.cell))
+        .map(|cell| cell.typ == CellType::Output)
+        .unwrap_or(false)
+}
+
+/// Digitises a cell's polarization with the thresholds of a truth table.
+/// With ternary options, the first two polarizations are read as a ternary
+/// state; otherwise the first is read as a binary one.
+fn read_cell(
+    views: &[SignalView],
+    sample: usize,
+    options: &DigitizeOptions,
+    ternary: Option<&TernaryOptions>,
+) -> Option<f64> {
+    let p1 = views.first()?.get(sample)?;
+    match ternary {
+        Some(ternary) => {
+            let p2 = match views.get(1) {
+                Some(view) => view.get(sample)?,
+                None => 0.0,
+            };
+            digitize_ternary(p1, p2, options, ternary).map(f64::from)
+        }
+        None => match digitize(p1, LogicValue::Unknown, options) {
+            LogicValue::One => Some(1.0),
+            LogicValue::Zero => Some(0.0),
+            LogicValue::Unknown => None,
+        },
+    }
+}
+
+/// Reads every requested cell, in the order of `settings.cells`, at the end
+/// of the hold phases of its clock zone shifted by its clock delay.
+pub fn cell_columns(
+    simulation: &dyn SignalSource,
+    settings: &TruthTableSettings,
+    holds: &[Vec<Range<usize>>],
+) -> Result<Vec<TruthTableColumn>, String> {
+    let (design, metadata) = (simulation.design(), simulation.metadata());
+    let options = settings.digitize_options();
+    let cell_clock_delay = parse_cell_clock_delay(&settings.cell_clock_delay)?;
+
+    settings
+        .cells
+        .iter()
+        .map(|cell| {
+            let stored_index = stored_cell_index(metadata, cell)?;
+            let views = (0..polarization_count(design, cell)?.min(2))
+                .map(|j| {
+                    simulation.signal_view(&SignalIndex {
+                        kind: SignalKind::Cell,
+                        index: stored_index,
+                        subindex: Some(j),
+                    })
+                })
+                .collect::<Result<Vec<_>, String>>()?;
+            let clock_zone = cell_clock_zone(design, cell)?;
+            let holds = &holds[clock_zone];
+            let clock_delay = cell_clock_delay.get(cell).copied().unwrap_or(0);
+
+            Ok(TruthTableColumn {
+                cell: Some(*cell),
+                name: cell_name(design, cell),
+                is_output: is_output_cell(design, cell),
+                clock_zone,
+                clock_delay,
+                values: (0..holds.len().saturating_sub(clock_delay))
+                    .map(|row| {
+                        let sample = row_sample(holds, row, clock_delay)?;
+                        read_cell(&views, sample, &options, settings.ternary.as_ref())
+                    })
+                    .collect(),
+            })
+        })
+        .collect()
+}
+
+/// Reads the truth table at the end of the hold phases in `holds`, as
+/// found by [`clock_holds`] with the settings' clock threshold.
+pub fn compute_truth_table(
+    simulation: &dyn SignalSource,
+    settings: &TruthTableSettings,
+    holds: &[Vec<Range<usize>>],
+) -> Result<TruthTableData, String> {
+    let mut columns = cell_columns(simulation, settings, holds)?;
+    columns.sort_by_key(|column| column.is_output);
+    columns.extend(derived_columns(simulation, settings, holds)?);
+
+    let rows = columns
+        .iter()
+        .map(|column| column.values.len())
+        .max()
+        .unwrap_or(0);
+    Ok(TruthTableData { columns, rows })
+}
+
+/// Reads derived signals at the end of the hold phases of their clock zone.
+fn derived_columns(
+    simulation: &dyn SignalSource,
+    settings: &TruthTableSettings,
+    holds: &[Vec<Range<usize>>],
+) -> Result<Vec<TruthTableColumn>, String> {
+    let options = settings.digitize_options();
+    let zero = match settings.ternary {
+        Some(_) => -1.0,
+        None => 0.0,
+    };
+
+    settings
+        .derived
+        .iter()
+        .map(|signal| {
+            let holds = holds
+                .get(signal.clock_zone)
+                .ok_or(format!("Clock zone {} does not exist", signal.clock_zone))?;
+            // Only the row samples are evaluated
+            let expr = SignalExpr::parse(&signal.expression)
+                .map_err(|err| format!("Derived signal '{}': {}", signal.name, err))?;
+            let samples = (0..holds.len())
+                .filter_map(|row| row_sample(holds, row, 0))
+                .map(|sample| {
+                    expr.eval_range(simulation, sample..sample + 1)
+                        .map(|values| values.first().copied())
+                })
+                .collect::<Result<Vec<Option<f64>>, String>>()
+                .map_err(|err| format!("Derived signal '{}': {}", signal.name, err))?;
+            Ok(TruthTableColumn {
+                cell: None,
+                name: signal.name.clone(),
+                is_output: true,
+                clock_zone: signal.clock_zone,
+                clock_delay: 0,
+                values: samples
+                    .iter()
+                    .map(
+                        |value| match digitize((*value)?, LogicValue::Unknown, &options) {
+                            LogicValue::One => Some(1.0),
+                            LogicValue::Zero => Some(zero),
+                            LogicValue::Unknown => None,
+                        },
+                    )
+                    .collect(),
+            })
+        })
+        .collect()
+}
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TruthTableFormat {
+    Csv,
+    Markdown,
+    Latex,
+}
+
+fn format_cell_value(value: Option<f64>) -> String {
+    match value {
+        Some(value) if value.fract() == 0.0 => format!("{}", value as i64),
+        Some(value) => value.to_string(),
+        None => "NaN".into(),
+    }
+}
+
+fn column_header(column: &TruthTableColumn) -> String {
+    match column.clock_delay {
+        0 => column.name.clone(),
+        delay => format!("{} (delay {})", column.name, delay),
+    }
+}
+
+fn escape_csv(field: &str) -> String {
+    if field.contains(',') || field.contains('"') || field.contains('\n') {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn escape_latex(field: &str) -> String {
+    field
+        .chars()
+        .map(|c| match c {
+            '\\' => "\\textbackslash{}".to_string(),
+            '&' | '%' | '$' | '#' | '_' | '{' | '}' => format!("\\{}", c),
+            '~' => "\\textasciitilde{}".to_string(),
+            '^' => "\\textasciicircum{}".to_string(),
+            c => c.to_string(),
+        })
+        .collect()
+}
+
+/// Renders a truth table. When `mismatched_rows` is given, an extra column
+/// marks the rows that differ from the expected function.
+pub fn render_truth_table(
+    table: &TruthTableData,
+    format: TruthTableFormat,
+    mismatched_rows: Option<&[usize]>,
+) -> String {
+    let headers: Vec<String> = table.columns.iter().map(column_header).collect();
+    let rows: Vec<Vec<String>> = (0..table.rows)
+        .map(|row| {
+            table
+                .columns
+                .iter()
+                .map(|column| format_cell_value(column.values.get(row).copied().flatten()))
+                .collect()
+        })
+        .collect();
+    let is_mismatch = |row: usize| mismatched_rows.map(|mismatched| mismatched.contains(&row));
+
+    let mut out = String::new();
+    match format {
+        TruthTableFormat::Csv => {
+            let mut header: Vec<String> = headers.iter().map(|h| escape_csv(h)).collect();
+            if mismatched_rows.is_some() {
+                header.push("mismatch".into());
+            }
+            out.push_str(&header.join(","));
+            out.push('\n');
+            for (i, row) in rows.iter().enumerate() {
+                let mut row = row.clone();
+                if let Some(mismatch) = is_mismatch(i) {
+                    row.push((mismatch as u8).to_string());
+                }
+                out.push_str(&row.join(","));
+                out.push('\n');
+            }
+        }
+        TruthTableFormat::Markdown => {
+            let mut header = headers.clone();
+            if mismatched_rows.is_some() {
+                header.push("Mismatch".into());
+            }
+            out.push_str(&format!("| {} |\n", header.join(" | ")));
+            out.push_str(&format!("|{}\n", " :---: |".repeat(header.len())));
+            for (i, row) in rows.iter().enumerate() {
+                let mut row = row.clone();
+                if let Some(mismatch) = is_mismatch(i) {
+                    row.push(if mismatch {
+                        "✗".into()
+                    } else {
+                        String::new()
+                    });
+                }
+                out.push_str(&format!("| {} |\n", row.join(" | ")));
+            }
+        }
+        TruthTableFormat::Latex => {
+            let num_inputs = table.inputs().count();
+            let num_outputs = table.outputs().count();
+            let mut spec = "c".repeat(num_inputs);
+            if num_inputs > 0 && num_outputs > 0 {
+                spec.push('|');
+            }
+            spec.push_str(&"c".repeat(num_outputs));
+            if mismatched_rows.is_some() {
+                spec.push_str("|c");
+            }
+
+            let mut header: Vec<String> = headers.iter().map(|h| escape_latex(h)).collect();
+            if mismatched_rows.is_some() {
+                header.push("Mismatch".into());
+            }
+            out.push_str(&format!("\\begin{{tabular}}{{{}}}\n\\hline\n", spec));
+            out.push_str(&format!("{} \\\\\n\\hline\n", header.join(" & ")));
+            for (i, row) in rows.iter().enumerate() {
+                let mut row = row.clone();
+                if let Some(mismatch) = is_mismatch(i) {
+                    row.push(if mismatch {
+                        "$\\times$".into()
+                    } else {
+                        String::new()
+                    });
+                }
+                out.push_str(&format!("{} \\\\\n", row.join(" & ")));
+            }
+            out.push_str("\\hline\n\\end{tabular}\n");
+        }
+    }
+    out
+}
+
+/// Rows whose outputs differ from the expected outputs, given per row in
+/// output column order. Unspecified expectations are not checked.
+pub fn mismatched_rows(table: &TruthTableData, expected_rows: &[Vec<Option<f64>>]) -> Vec<usize> {
+    (0..table.rows)
+        .filter(|row| match expected_rows.get(*row) {
+            Some(expected) => table
+                .outputs()
+                .zip(expected.iter())
+                .any(|(column, expected)| match expected {
+                    Some(expected) => column.values.get(*row).copied().flatten() != Some(*expected),
+                    None => false,
+                }),
+            None => false,
+        })
+        .collect()
+}
+
+/// Exports a truth table. Rows are marked as mismatched against
+/// `expected_rows`, the expected outputs of each row, and against
+/// `expectations`, the expected function of each output cell.
+#[tauri::command(async)]
+pub fn export_truth_table(
+    cache: State<'_, SimulationCache>,
+    filename: String,
+    output_filename: String,
+    settings: TruthTableSettings,
+    format: TruthTableFormat,
+    expected_rows: Option<Vec<Vec<Option<f64>>>>,
+    expectations: Option<HashMap<String, OutputExpectation>>,
+) -> Result<(), String> {
+    let access = cache.access(&filename)?;
+    let simulation = access.source();
+    let holds = clock_holds(simulation, settings.clock_threshold)?;
+    let table = compute_truth_table(simulation, &settings, &holds)?;
+
+    let mut mismatched = expected_rows.map(|expected| mismatched_rows(&table, &expected));
+    if let Some(expectations) = expectations {
+        let verification = verify_table(&table, &holds, &parse_expectations(&expectations)?)?;
+        let rows = mismatched.get_or_insert_with(Vec::new);
+        rows.extend(verification.mismatched_rows());
+        rows.sort_unstable();
+        rows.dedup();
+    }
+    let rendered = render_truth_table(&table, format, mismatched.as_deref());
+
+    let mut file = File::create(output_filename).map_err(|_err| "Failed to create file")?;
+    file.write_all(rendered.as_bytes())
+        .map_err(|_err| "Failed to write to file")?;
+    Ok(())
+}
@@ -0,0 +1,187 @@
+use crate::digital::{digitize, DigitizeOptions, LogicValue};
+use crate::signal::{cell_signals, clock_name, SampleWindow, SignalIndex, SignalKind, CLOCK_COUNT};
+use crate::sim_cache::SimulationCache;
+use crate::timing::simulation_timing;
+use qca_core::objects::cell::QCACellIndex;
+use serde::Deserialize;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use tauri::State;
+
+const VCD_TIME_UNITS: [(&str, f64); 6] = [
+    ("1 s", 1.0),
+    ("1 ms", 1e-3),
+    ("1 us", 1e-6),
+    ("1 ns", 1e-9),
+    ("1 ps", 1e-12),
+    ("1 fs", 1e-15),
+];
+/// Minimal number of time units per sample, so rounding keeps sample times
+/// accurate.
+const VCD_TICKS_PER_SAMPLE: f64 = 1000.0;
+
+#[derive(Deserialize)]
+pub struct VcdExportOptions {
+    #[serde(flatten)]
+    digitize: DigitizeOptions,
+    start: Option<usize>,
+    end: Option<usize>,
+}
+
+/// Short printable identifier codes, as used by VCD writers.
+fn vcd_identifier(mut index: usize) -> String {
+    let mut identifier = String::new();
+    loop {
+        identifier.push((b'!' + (index % 94) as u8) as char);
+        index /= 94;
+        if index == 0 {
+            return identifier;
+        }
+        index -= 1;
+    }
+}
+
+fn vcd_name(name: &str) -> String {
+    name.split_whitespace().collect::<Vec<&str>>().join("_")
+}
+
+/// Picks the timescale and the number of its units per sample.
+fn vcd_timescale(sample_period: Option<f64>) -> (&'static str, Option<f64>) {
+    match sample_period {
+        Some(period) => {
+            let (unit, seconds) = VCD_TIME_UNITS
+                .iter()
+                .find(|(_, seconds)| period / seconds >= VCD_TICKS_PER_SAMPLE)
+                .unwrap_or(&VCD_TIME_UNITS[VCD_TIME_UNITS.len() - 1]);
+            (unit, Some(period / seconds))
+        }
+        None => ("1 s", None),
+    }
+}
+
+#[tauri::command(async)]
+pub fn export_simulation_vcd(
+    cache: State<'_, SimulationCache>,
+    filename: String,
+    output_filename: String,
+    cells: Option<Vec<QCACellIndex>>,
+    options: VcdExportOptions,
+) -> Result<(), String> {
+    let access = cache.access(&filename)?;
+    let simulation = access.source();
+    let (design, metadata) = (simulation.design(), simulation.metadata());
+    let num_samples = metadata.num_samples;
+    let window = SampleWindow::new(options.start, options.end, None, num_samples)?;
+
+    let cells = cells.unwrap_or(metadata.stored_cells.clone());
+    let mut cell_signal_list = vec![];
+    for cell in &cells {
+        let stored_index = metadata
+            .stored_cells
+            .iter()
+            .position(|stored| stored == cell)
+            .ok_or(format!(
+                "Cell {}-{} is not stored in the simulation",
+                cell.layer, cell.cell
+            ))?;
+        cell_signal_list.extend(cell_signals(design, stored_index, cell)?);
+    }
+    let cell_views = cell_signal_list
+        .iter()
+        .map(|signal| simulation.signal_view(&signal.index))
+        .collect::<Result<Vec<_>, String>>()?;
+    let clock_views = (0..CLOCK_COUNT)
+        .map(|clock| {
+            let index = SignalIndex {
+                kind: SignalKind::Clock,
+                index: clock,
+                subindex: None,
+            };
+            simulation.signal_view(&index)
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    // Without timing, samples are written as ticks of 1 s
+    let sample_period = simulation_timing(design, num_samples)
+        .map(|timing| timing.sample_period())
+        .ok();
+    let (timescale, ticks_per_sample) = vcd_timescale(sample_period);
+    let sample_tick = |sample: usize| match ticks_per_sample {
+        Some(ticks) => (sample as f64 * ticks).round() as u64,
+        None => sample as u64,
+    };
+
+    let file = File::create(output_filename).map_err(|_err| "Failed to create file")?;
+    let mut writer = BufWriter::new(file);
+    let write_err = |_err| "Failed to write to file".to_string();
+
+    writeln!(writer, "$date {} $end", metadata.start_time).map_err(write_err)?;
+    writeln!(
+        writer,
+        "$version QCAForge {} $end",
+        env!("CARGO_PKG_VERSION")
+    )
+    .map_err(write_err)?;
+    if ticks_per_sample.is_none() {
+        writeln!(writer, "$comment time is the sample index $end").map_err(write_err)?;
+    }
+    writeln!(writer, "$timescale {} $end", timescale).map_err(write_err)?;
+    writeln!(writer, "$scope module qca $end").map_err(write_err)?;
+    for (i, signal) in cell_signal_list.iter().enumerate() {
+        writeln!(
+            writer,
+            "$var wire 1 {} {} $end",
+            vcd_identifier(i),
+            vcd_name(&signal.name)
+        )
+        .map_err(write_err)?;
+    }
+    for clock in 0..clock_views.len() {
+        writeln!(
+            writer,
+            "$var real 64 {} {} $end",
+            vcd_identifier(cell_views.len() + clock),
+            vcd_name(&clock_name(clock))
+        )
+        .map_err(write_err)?;
+    }
+    writeln!(writer, "$upscope $end").map_err(write_err)?;
+    writeln!(writer, "$enddefinitions $end").map_err(write_err)?;
+
+    let mut cell_values: Vec<Option<LogicValue>> = vec![None; cell_views.len()];
+    let mut clock_values: Vec<Option<f64>> = vec![None; clock_views.len()];
+    for sample in window.indices() {
+        let mut changes = vec![];
+        for (i, view) in cell_views.iter().enumerate() {
+            let polarization = view.get(sample).unwrap_or(f64::NAN);
+            let previous = cell_values[i].unwrap_or(LogicValue::Unknown);
+            let value = digitize(polarization, previous, &options.digitize);
+            if cell_values[i] != Some(value) {
+                cell_values[i] = Some(value);
+                changes.push(format!("{}{}", value.as_char(), vcd_identifier(i)));
+            }
+        }
+        for (clock, view) in clock_views.iter().enumerate() {
+            let value = view.get(sample).unwrap_or(f64::NAN);
+            if clock_values[clock] != Some(value) {
+                clock_values[clock] = Some(value);
+                changes.push(format!(
+                    "r{} {}",
+                    value,
+                    vcd_identifier(cell_views.len() + clock)
+                ));
+            }
+        }
+
+        if !changes.is_empty() {
+            writeln!(writer, "#{}", sample_tick(sample)).map_err(write_err)?;
+            for change in changes {
+                writeln!(writer, "{}", change).map_err(write_err)?;
+            }
+        }
+    }
+    writeln!(writer, "#{}", sample_tick(window.end)).map_err(write_err)?;
+
+    writer.flush().map_err(write_err)?;
+    Ok(())
+}
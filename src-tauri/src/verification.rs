@@ -0,0 +1,314 @@
+use crate::clocking::{clock_holds, row_sample};
+use crate::logic_expr::LogicExpr;
+use crate::sim_cache::SimulationCache;
+use crate::truth_table::{compute_truth_table, TruthTableData, TruthTableSettings};
+use qca_core::objects::cell::QCACellIndex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::ops::Range;
+use std::str::FromStr;
+use tauri::State;
+
+/// The function an output cell is expected to implement. Stored in the
+/// design file, keyed by the output cell index.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum OutputExpectation {
+    /// A Boolean expression over input labels, see [`LogicExpr`].
+    Expression { expression: String },
+    /// Expected outputs for every combination of `inputs`, with the first
+    /// input as the most significant bit.
+    Table {
+        inputs: Vec<String>,
+        outputs: Vec<Option<bool>>,
+    },
+    /// Expected ternary outputs for every combination of `inputs`, counting
+    /// in base 3 with the digits −1, 0, +1 and the first input as the most
+    /// significant digit.
+    TernaryTable {
+        inputs: Vec<String>,
+        outputs: Vec<Option<i32>>,
+    },
+}
+
+enum CompiledExpectation {
+    Expression(LogicExpr),
+    Table {
+        inputs: Vec<String>,
+        outputs: Vec<Option<bool>>,
+    },
+    TernaryTable {
+        inputs: Vec<String>,
+        outputs: Vec<Option<i32>>,
+    },
+}
+
+impl CompiledExpectation {
+    fn new(expectation: &OutputExpectation) -> Result<CompiledExpectation, String> {
+        Ok(match expectation {
+            OutputExpectation::Expression { expression } => {
+                CompiledExpectation::Expression(LogicExpr::parse(expression)?)
+            }
+            OutputExpectation::Table { inputs, outputs } => {
+                if outputs.len() != 1 << inputs.len() {
+                    return Err(format!(
+                        "Expected table over {} inputs needs {} rows",
+                        inputs.len(),
+                        1 << inputs.len()
+                    ));
+                }
+                CompiledExpectation::Table {
+                    inputs: inputs.clone(),
+                    outputs: outputs.clone(),
+                }
+            }
+            OutputExpectation::TernaryTable { inputs, outputs } => {
+                let rows = 3_usize.pow(inputs.len() as u32);
+                if outputs.len() != rows {
+                    return Err(format!(
+                        "Expected ternary table over {} inputs needs {} rows",
+                        inputs.len(),
+                        rows
+                    ));
+                }
+                CompiledExpectation::TernaryTable {
+                    inputs: inputs.clone(),
+                    outputs: outputs.clone(),
+                }
+            }
+        })
+    }
+
+    /// Reads a truth table value as the logic value this expectation works
+    /// with: 0/1 for binary functions and −1/0/+1 for ternary ones.
+    fn to_logic(&self, value: Option<f64>) -> Option<i32> {
+        let value = value.filter(|value| !value.is_nan())?;
+        match self {
+            CompiledExpectation::TernaryTable { .. } => Some(value.round().clamp(-1.0, 1.0) as i32),
+            _ => Some((value > 0.0) as i32),
+        }
+    }
+
+    fn expected(&self, inputs: &HashMap<String, Option<f64>>) -> Result<Option<i32>, String> {
+        let input = |label: &String| {
+            inputs
+                .get(label)
+                .map(|value| self.to_logic(*value))
+                .ok_or(format!("Unknown input '{}'", label))
+        };
+        match self {
+            CompiledExpectation::Expression(expr) => {
+                let inputs = inputs
+                    .iter()
+                    .map(|(label, value)| (label.clone(), self.to_logic(*value).map(|v| v > 0)))
+                    .collect();
+                Ok(expr.eval(&inputs)?.map(|value| value as i32))
+            }
+            CompiledExpectation::Table {
+                inputs: labels,
+                outputs,
+            } => {
+                let mut index = 0;
+                for label in labels {
+                    match input(label)? {
+                        Some(value) => index = (index << 1) | value as usize,
+                        None => return Ok(None),
+                    }
+                }
+                Ok(outputs[index].map(|value| value as i32))
+            }
+            CompiledExpectation::TernaryTable {
+                inputs: labels,
+                outputs,
+            } => {
+                let mut index = 0;
+                for label in labels {
+                    match input(label)? {
+                        Some(value) => index = index * 3 + (value + 1) as usize,
+                        None => return Ok(None),
+                    }
+                }
+                Ok(outputs[index])
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RowStatus {
+    Pass,
+    Fail,
+    /// An input or the output has no defined logic value.
+    Unknown,
+}
+
+#[derive(Serialize)]
+pub struct RowVerification {
+    row: usize,
+    expected: Option<i32>,
+    actual: Option<i32>,
+    status: RowStatus,
+    /// Sample each truth table column was read at, in column order.
+    samples: Vec<Option<usize>>,
+}
+
+#[derive(Serialize)]
+pub struct OutputVerification {
+    cell: QCACellIndex,
+    name: String,
+    passed: bool,
+    mismatched_rows: Vec<usize>,
+    /// Rows whose expected or actual output is unknown.
+    unknown_rows: Vec<usize>,
+    rows: Vec<RowVerification>,
+}
+
+#[derive(Serialize)]
+pub struct TruthTableVerification {
+    passed: bool,
+    columns: Vec<String>,
+    outputs: Vec<OutputVerification>,
+}
+
+impl TruthTableVerification {
+    pub fn passed(&self) -> bool {
+        self.passed
+    }
+
+    pub fn mismatched_rows(&self) -> Vec<usize> {
+        let mut rows: Vec<usize> = self
+            .outputs
+            .iter()
+            .flat_map(|output| output.mismatched_rows.iter().copied())
+            .collect();
+        rows.sort_unstable();
+        rows.dedup();
+        rows
+    }
+
+    pub fn unknown_rows(&self) -> Vec<usize> {
+        let mut rows: Vec<usize> = self
+            .outputs
+            .iter()
+            .flat_map(|output| output.unknown_rows.iter().copied())
+            .collect();
+        rows.sort_unstable();
+        rows.dedup();
+        rows
+    }
+}
+
+pub fn parse_expectations(
+    expectations: &HashMap<String, OutputExpectation>,
+) -> Result<HashMap<QCACellIndex, OutputExpectation>, String> {
+    expectations
+        .iter()
+        .map(|(k, v)| {
+            let cell_index =
+                QCACellIndex::from_str(k).map_err(|_err| format!("Invalid cell index '{}'", k))?;
+            Ok((cell_index, v.clone()))
+        })
+        .collect()
+}
+
+/// Checks the outputs of `table` against their expectations, in cell index
+/// order. `holds` are the hold intervals the table was read with.
+pub fn verify_table(
+    table: &TruthTableData,
+    holds: &[Vec<Range<usize>>],
+    expectations: &HashMap<QCACellIndex, OutputExpectation>,
+) -> Result<TruthTableVerification, String> {
+    let column_holds: Vec<&Vec<Range<usize>>> = table
+        .columns
+        .iter()
+        .map(|column| &holds[column.clock_zone])
+        .collect();
+
+    let mut expectations: Vec<(&QCACellIndex, &OutputExpectation)> = expectations.iter().collect();
+    expectations.sort_by_key(|(cell, _)| (cell.layer, cell.cell));
+
+    let mut outputs = vec![];
+    for (cell, expectation) in expectations {
+        let column = table
+            .columns
+            .iter()
+            .find(|column| column.cell == Some(*cell))
+            .ok_or(format!(
+                "Cell {}-{} is not part of the truth table",
+                cell.layer, cell.cell
+            ))?;
+        let expectation = CompiledExpectation::new(expectation)?;
+
+        let mut rows = vec![];
+        for row in 0..table.rows {
+            let inputs: HashMap<String, Option<f64>> = table
+                .inputs()
+                .map(|input| (input.name.clone(), input.values.get(row).copied().flatten()))
+                .collect();
+            let expected = expectation.expected(&inputs)?;
+            let actual = expectation.to_logic(column.values.get(row).copied().flatten());
+            let status = match (expected, actual) {
+                (Some(expected), Some(actual)) if expected == actual => RowStatus::Pass,
+                (Some(_), Some(_)) => RowStatus::Fail,
+                _ => RowStatus::Unknown,
+            };
+            let samples = table
+                .columns
+                .iter()
+                .zip(&column_holds)
+                .map(|(column, holds)| row_sample(holds, row, column.clock_delay))
+                .collect();
+
+            rows.push(RowVerification {
+                row,
+                expected,
+                actual,
+                status,
+                samples,
+            });
+        }
+
+        outputs.push(OutputVerification {
+            cell: *cell,
+            name: column.name.clone(),
+            passed: rows.iter().all(|row| row.status == RowStatus::Pass),
+            mismatched_rows: rows
+                .iter()
+                .filter(|row| row.status == RowStatus::Fail)
+                .map(|row| row.row)
+                .collect(),
+            unknown_rows: rows
+                .iter()
+                .filter(|row| row.status == RowStatus::Unknown)
+                .map(|row| row.row)
+                .collect(),
+            rows,
+        });
+    }
+
+    Ok(TruthTableVerification {
+        passed: outputs.iter().all(|output| output.passed),
+        columns: table
+            .columns
+            .iter()
+            .map(|column| column.name.clone())
+            .collect(),
+        outputs,
+    })
+}
+
+#[tauri::command(async)]
+pub fn verify_truth_table(
+    cache: State<'_, SimulationCache>,
+    filename: String,
+    settings: TruthTableSettings,
+    expectations: HashMap<String, OutputExpectation>,
+) -> Result<TruthTableVerification, String> {
+    let access = cache.access(&filename)?;
+    let simulation = access.source();
+    let holds = clock_holds(simulation, settings.clock_threshold)?;
+    let table = compute_truth_table(simulation, &settings, &holds)?;
+
+    verify_table(&table, &holds, &parse_expectations(&expectations)?)
+}
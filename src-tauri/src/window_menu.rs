@@ -18,6 +18,10 @@ fn create_file_menu(app: &mut App) -> Submenu<Wry> {
         .id("openSimulation")
         .build(app)
         .unwrap();
+    let file_merge_simulations = MenuItemBuilder::new("Merge simulations")
+        .id("mergeSimulations")
+        .build(app)
+        .unwrap();
     let file_save_file = MenuItemBuilder::new("Save")
         .id("saveFile")
         .accelerator("CmdOrCtrl+S")
@@ -40,6 +44,7 @@ fn create_file_menu(app: &mut App) -> Submenu<Wry> {
             &PredefinedMenuItem::separator(app).unwrap(),
             &file_open_design,
             &file_open_simulation,
+            &file_merge_simulations,
             &PredefinedMenuItem::separator(app).unwrap(),
             &file_save_file,
             &file_save_file_as,
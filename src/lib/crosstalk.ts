@@ -0,0 +1,41 @@
+import { invoke } from "@tauri-apps/api/core";
+import type { CellIndex } from "./Cell";
+import type { QCADesign } from "./qca-design";
+
+export interface CrosstalkOptions {
+	radius: number;
+	relative_permittivity?: number;
+	coupling_threshold?: number;
+}
+
+export type InteractionKind =
+	| "neighbour"
+	| "diagonal"
+	| "wire_gap"
+	| "crossover"
+	| "distant";
+
+export interface Interaction {
+	a: CellIndex;
+	b: CellIndex;
+	kind: InteractionKind;
+	distance: number;
+	kink_energy: number;
+	relative_strength: number;
+	flagged: boolean;
+}
+
+export interface CrosstalkReport {
+	interactions: Interaction[];
+	flagged: number[];
+}
+
+export function analyzeCrosstalk(
+	design: QCADesign,
+	options: CrosstalkOptions,
+): Promise<CrosstalkReport> {
+	return invoke("analyze_crosstalk", {
+		qcaDesign: design,
+		options: options,
+	}) as Promise<CrosstalkReport>;
+}
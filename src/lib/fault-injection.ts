@@ -0,0 +1,55 @@
+import { invoke } from "@tauri-apps/api/core";
+import type { CellIndex } from "./Cell";
+import type { OutputExpectation, QCADesign } from "./qca-design";
+import type { TruthTableSettings } from "./qca-simulation";
+
+export interface FaultOptions {
+	displacement?: number;
+	rotation?: number;
+	cells?: CellIndex[];
+}
+
+export type FaultKind =
+	| "missing"
+	| "displaced"
+	| "rotated"
+	| "stuck_positive"
+	| "stuck_negative";
+
+export interface FaultResult {
+	kind: FaultKind;
+	/** `null` if the variant could not be simulated or verified. */
+	passed: boolean | null;
+	mismatched_rows: number[];
+	error: string | null;
+}
+
+export interface CellCriticality {
+	cell: CellIndex;
+	faults: FaultResult[];
+	criticality: number | null;
+	failed: number;
+}
+
+export interface FaultAnalysisProgress {
+	current: number;
+	total: number;
+}
+
+export function analyzeFaultCriticality(
+	design: QCADesign,
+	settings: TruthTableSettings,
+	expectations: { [cell: string]: OutputExpectation },
+	options?: FaultOptions,
+): Promise<CellCriticality[]> {
+	return invoke("analyze_fault_criticality", {
+		qcaDesign: design,
+		settings: settings,
+		expectations: expectations,
+		options: options,
+	}) as Promise<CellCriticality[]>;
+}
+
+export function cancelFaultAnalysis(): Promise<void> {
+	return invoke("cancel_fault_analysis");
+}
@@ -33,10 +33,25 @@ export interface QCADesign {
 	simulation_settings: SimulationSettings;
 }
 
+export type OutputExpectation =
+	| { type: "expression"; expression: string }
+	| { type: "table"; inputs: string[]; outputs: (boolean | null)[] }
+	| { type: "ternary_table"; inputs: string[]; outputs: (number | null)[] };
+
+export interface DerivedSignal {
+	name: string;
+	expression: string;
+	clock_zone?: number;
+}
+
 export interface QCADesignFile {
 	qca_forge_version: string;
 	design: QCADesign;
 	designer_properties: DesignViewProps;
+	/** Expected function of output cells, keyed by cell index. */
+	expectations?: { [cell: string]: OutputExpectation };
+	/** Derived signal expressions used in analyses of this design. */
+	derived_signals?: DerivedSignal[];
 }
 
 export interface NewDesignConfig {
@@ -125,6 +140,7 @@ export function createDefaultDesignViewProps(): DesignViewProps {
 export async function createQCADesignFile(
 	design: QCADesign,
 	designerProps: DesignViewProps | undefined,
+	analysis: Pick<QCADesignFile, "expectations" | "derived_signals"> = {},
 ): Promise<QCADesignFile> {
 	const qca_forge_ver = await getVersion();
 	const qca_design_view_props =
@@ -133,6 +149,8 @@ export async function createQCADesignFile(
 		qca_forge_version: qca_forge_ver,
 		design: design,
 		designer_properties: qca_design_view_props,
+		expectations: analysis.expectations,
+		derived_signals: analysis.derived_signals,
 	};
 }
 
@@ -4,9 +4,15 @@ import {
 	deserializeQCADesign,
 	deserializeQCADesignFile,
 	type CommonSimulationModelSettings,
+	type DerivedSignal,
+	type OutputExpectation,
 	type QCADesign,
 } from "./qca-design";
 import { v4 as uuidv4 } from "uuid";
+import {
+	parseSimulationFrame,
+	type SimulationFrame,
+} from "./simulation-frame";
 
 export const QCA_SIMULATION_FILE_EXTENSION = "qcs";
 
@@ -18,6 +24,7 @@ export interface TimeDelta {
 export enum SignalType {
 	CLOCK = 0,
 	CELL = 1,
+	DERIVED = 2,
 }
 
 export interface SignalIndex {
@@ -89,6 +96,178 @@ export function getInputLabel(
 	}
 }
 
+export type DownsampleMethod = "min_max" | "lttb";
+
+export type DownsampledData =
+	| {
+			method: "min_max";
+			bucket_start: number[];
+			min: number[];
+			min_index: number[];
+			max: number[];
+			max_index: number[];
+	  }
+	| {
+			method: "lttb";
+			indices: number[];
+			values: number[];
+	  };
+
+export interface DownsampledSignal {
+	index: SignalIndex;
+	name: string;
+	data: DownsampledData;
+}
+
+export interface TernaryLevel {
+	state: number;
+	polarization: [number, number];
+}
+
+export interface TernaryOptions {
+	levels?: TernaryLevel[];
+	tolerance?: number;
+}
+
+export interface TruthTableSettings {
+	cells: CellIndex[];
+	cell_clock_delay: { [cell: string]: number };
+	clock_threshold: number;
+	logical_threshold: number;
+	value_threshold: number;
+	ternary?: TernaryOptions;
+	derived?: DerivedSignal[];
+}
+
+export type RowStatus = "pass" | "fail" | "unknown";
+
+export interface RowVerification {
+	row: number;
+	expected: number | null;
+	actual: number | null;
+	status: RowStatus;
+	samples: (number | null)[];
+}
+
+export interface OutputVerification {
+	cell: CellIndex;
+	name: string;
+	passed: boolean;
+	mismatched_rows: number[];
+	/** Rows whose expected or actual output is unknown. */
+	unknown_rows: number[];
+	rows: RowVerification[];
+}
+
+export interface TruthTableVerification {
+	passed: boolean;
+	columns: string[];
+	outputs: OutputVerification[];
+}
+
+export interface ClockDelaySuggestion {
+	cell: CellIndex;
+	delay: number | null;
+	confidence: number;
+	scores: (number | null)[];
+}
+
+export interface Histogram {
+	min: number;
+	max: number;
+	bin_width: number;
+	counts: number[];
+	separations: number[];
+}
+
+export interface ThresholdSuggestion {
+	clock_threshold: number | null;
+	logical_threshold: number | null;
+	value_threshold: number | null;
+	hold_histogram: Histogram;
+	polarization_histogram: Histogram;
+	magnitude_histogram: Histogram;
+}
+
+export interface CycleStatistics {
+	cycle: number;
+	start: number;
+	end: number;
+	hold_value: number;
+	transition: "rise" | "fall" | "hold";
+	onset: number | null;
+	settling_samples: number;
+	settling_time: number | null;
+	overshoot: number;
+}
+
+export interface SignalStatistics {
+	index: SignalIndex;
+	name: string;
+	min: number;
+	max: number;
+	mean: number;
+	rms: number;
+	final_value: number;
+	cycles: CycleStatistics[];
+}
+
+export interface CellPolarization {
+	cell: CellIndex;
+	polarization?: number;
+}
+
+export interface DigitizeOptions {
+	logical_threshold: number;
+	value_threshold: number;
+	hysteresis?: number;
+}
+
+export interface PropagationEvent {
+	input_sample: number;
+	input_transition: "rise" | "fall";
+	output_sample: number | null;
+	output_transition: "rise" | "fall" | null;
+	delay_samples: number | null;
+	delay_time: number | null;
+}
+
+export interface PropagationDelay {
+	events: PropagationEvent[];
+	mean_delay_samples: number | null;
+	max_delay_samples: number | null;
+	mean_delay_time: number | null;
+	max_delay_time: number | null;
+}
+
+export interface PolarizationSnapshot {
+	start: number;
+	end: number;
+	clocks: number[];
+	cells: { [cell: string]: number[] };
+}
+
+export type ClockPhase = "switch" | "hold" | "release" | "relax";
+
+export interface ClockAnnotation {
+	cycles: number[];
+	phases: ClockPhase[][];
+	cycle_ranges: { start: number; end: number }[];
+}
+
+export type AnimationFormat = "gif" | "apng" | "png_sequence";
+
+export interface AnimationOptions {
+	format: AnimationFormat;
+	width: number;
+	height: number;
+	frame_rate: number;
+	start?: number;
+	end?: number;
+	frame_count?: number;
+	layer?: number;
+}
+
 export interface QCASimulationMetadata {
 	qca_core_version: string;
 	start_time: Date;
@@ -115,6 +294,8 @@ export class QCASimulation {
 		| [Float64Array, Float64Array, Float64Array, Float64Array]
 		| undefined;
 	private _cellData: Map<string, Float64Array[]>;
+	private _derivedSignals: DerivedSignal[];
+	private _derivedData: Map<number, Float64Array>;
 
 	constructor(
 		filename: string,
@@ -128,6 +309,8 @@ export class QCASimulation {
 
 		this._clockData = undefined;
 		this._cellData = new Map<string, Float64Array[]>();
+		this._derivedSignals = [];
+		this._derivedData = new Map<number, Float64Array>();
 	}
 
 	public get session_id(): string {
@@ -142,6 +325,23 @@ export class QCASimulation {
 	public get metadata(): QCASimulationMetadata {
 		return this._metadata;
 	}
+	public get derivedSignals(): DerivedSignal[] {
+		return this._derivedSignals;
+	}
+	public set derivedSignals(signals: DerivedSignal[]) {
+		this._derivedSignals = signals;
+		this._derivedData.clear();
+	}
+
+	public async getDerivedData(index: number): Promise<Float64Array> {
+		if (!this._derivedData.has(index)) {
+			const samples = await this.evaluateSignalExpression(
+				this._derivedSignals[index].expression,
+			);
+			this._derivedData.set(index, Float64Array.from(samples));
+		}
+		return this._derivedData.get(index)!;
+	}
 
 	public async getClockData(index: number): Promise<Float64Array> {
 		if (this._clockData === undefined) {
@@ -188,6 +388,8 @@ export class QCASimulation {
 								polarizationIndex,
 							),
 						];
+					case SignalType.DERIVED:
+						return [await this.getDerivedData(signalIndex.index)];
 				}
 			default:
 				throw new Error("Invalid input type");
@@ -243,6 +445,13 @@ export class QCASimulation {
 			}
 		}
 
+		this._derivedSignals.forEach((signal, i) => {
+			signals.push({
+				index: { type: SignalType.DERIVED, index: i },
+				name: signal.name,
+			});
+		});
+
 		return signals;
 	}
 
@@ -273,6 +482,230 @@ export class QCASimulation {
 		throw new Error("Signal not found");
 	}
 
+	public close(): Promise<void> {
+		this._clockData = undefined;
+		this._cellData.clear();
+		this._derivedData.clear();
+		return invoke("close_simulation", { filename: this._filename }).then(
+			() => {},
+		);
+	}
+
+	public async loadWindow(
+		start: number,
+		end: number,
+		stride: number = 1,
+		indices: number[] | undefined = undefined,
+		derived: DerivedSignal[] | undefined = undefined,
+	): Promise<SimulationFrame> {
+		const result = await invoke("load_simulation_window", {
+			filename: this._filename,
+			indices: indices ?? null,
+			start: start,
+			end: end,
+			stride: stride,
+			dtype: null,
+			derived: derived ?? null,
+		});
+		return parseSimulationFrame(result as ArrayBuffer);
+	}
+
+	/** The signals plotted for an input, in the order of `getInputData`. */
+	public getInputSignals(input: PanelInput): SignalIndex[] {
+		if (input.type === InputType.SIGNAL) {
+			return [input.index as SignalIndex];
+		}
+		const cellIndex = input.index as CellIndex;
+		const stored = this._metadata.stored_cells.findIndex(
+			(cell) => cell.toString() === cellIndex.toString(),
+		);
+		const archId =
+			this._design.layers[cellIndex.layer].cell_architecture_id;
+		const arch = this._design.cell_architectures.get(archId)!;
+		return Array.from({ length: arch.dot_count / 4 }, (_, i) => ({
+			type: SignalType.CELL,
+			index: stored,
+			subindex: i,
+		}));
+	}
+
+	/**
+	 * Loads a window of samples for the given inputs, returning the signals
+	 * of each input in the same order as `getInputData`.
+	 */
+	public async loadInputWindow(
+		inputs: PanelInput[],
+		start: number,
+		end: number,
+		stride: number = 1,
+	): Promise<{ frame: SimulationFrame; data: Float64Array[][] }> {
+		const inputSignals = inputs.map((input) =>
+			this.getInputSignals(input),
+		);
+		const signals = inputSignals.flat();
+		const indices = signals
+			.filter((signal) => signal.type === SignalType.CELL)
+			.map((signal) => signal.index);
+		const needsDerived = signals.some(
+			(signal) => signal.type === SignalType.DERIVED,
+		);
+
+		const frame = await this.loadWindow(
+			start,
+			end,
+			stride,
+			[...new Set(indices)],
+			needsDerived ? this._derivedSignals : undefined,
+		);
+		const data = inputSignals.map((signals) =>
+			signals.map((signalIndex) => {
+				const signal = frame.signals.find((signal) => {
+					if (signal.kind !== signalIndex.type) return false;
+					if (signalIndex.type === SignalType.CELL) {
+						return (
+							signal.cell?.toString() ===
+								this._metadata.stored_cells[
+									signalIndex.index
+								].toString() &&
+							signal.polarizationIndex === signalIndex.subindex
+						);
+					}
+					// Clocks and derived signals store their index as the clock
+					return signal.clock === signalIndex.index;
+				});
+				if (!signal) {
+					throw new Error("Signal not found");
+				}
+				return signal.data;
+			}),
+		);
+		return { frame, data };
+	}
+
+	public downsampleSignals(
+		signals: SignalIndex[],
+		width: number,
+		method: DownsampleMethod,
+		start: number | undefined = undefined,
+		end: number | undefined = undefined,
+	): Promise<DownsampledSignal[]> {
+		return invoke("downsample_signals", {
+			filename: this._filename,
+			signals: signals,
+			width: width,
+			method: method,
+			start: start ?? null,
+			end: end ?? null,
+		}) as Promise<DownsampledSignal[]>;
+	}
+
+	public verifyTruthTable(
+		settings: TruthTableSettings,
+		expectations: { [cell: string]: OutputExpectation },
+	): Promise<TruthTableVerification> {
+		return invoke("verify_truth_table", {
+			filename: this._filename,
+			settings: settings,
+			expectations: expectations,
+		}) as Promise<TruthTableVerification>;
+	}
+
+	public suggestClockDelays(
+		settings: TruthTableSettings,
+		maxDelay: number | undefined = undefined,
+	): Promise<ClockDelaySuggestion[]> {
+		return invoke("suggest_clock_delays", {
+			filename: this._filename,
+			settings: settings,
+			maxDelay: maxDelay ?? null,
+		}) as Promise<ClockDelaySuggestion[]>;
+	}
+
+	public suggestThresholds(
+		cells: CellIndex[] | undefined = undefined,
+		bins: number | undefined = undefined,
+	): Promise<ThresholdSuggestion> {
+		return invoke("suggest_thresholds", {
+			filename: this._filename,
+			cells: cells ?? null,
+			bins: bins ?? null,
+		}) as Promise<ThresholdSuggestion>;
+	}
+
+	public signalStatistics(
+		clockThreshold: number,
+		cells: CellIndex[] | undefined = undefined,
+		tolerance: number | undefined = undefined,
+	): Promise<SignalStatistics[]> {
+		return invoke("signal_statistics", {
+			filename: this._filename,
+			cells: cells ?? null,
+			clockThreshold: clockThreshold,
+			tolerance: tolerance ?? null,
+		}) as Promise<SignalStatistics[]>;
+	}
+
+	public measurePropagationDelay(
+		input: CellPolarization,
+		output: CellPolarization,
+		options: DigitizeOptions,
+		clockThreshold: number,
+	): Promise<PropagationDelay> {
+		return invoke("measure_propagation_delay", {
+			filename: this._filename,
+			input: input,
+			output: output,
+			options: options,
+			clockThreshold: clockThreshold,
+		}) as Promise<PropagationDelay>;
+	}
+
+	public polarizationSnapshot(
+		sample: number,
+		end: number | undefined = undefined,
+	): Promise<PolarizationSnapshot> {
+		return invoke("polarization_snapshot", {
+			filename: this._filename,
+			sample: sample,
+			end: end ?? null,
+		}) as Promise<PolarizationSnapshot>;
+	}
+
+	public evaluateSignalExpression(
+		expression: string,
+		start: number | undefined = undefined,
+		end: number | undefined = undefined,
+		stride: number | undefined = undefined,
+	): Promise<number[]> {
+		return invoke("evaluate_signal_expression", {
+			filename: this._filename,
+			expression: expression,
+			start: start ?? null,
+			end: end ?? null,
+			stride: stride ?? null,
+		}) as Promise<number[]>;
+	}
+
+	public annotateClockPhases(
+		clockThreshold: number,
+	): Promise<ClockAnnotation> {
+		return invoke("annotate_clock_phases", {
+			filename: this._filename,
+			clockThreshold: clockThreshold,
+		}) as Promise<ClockAnnotation>;
+	}
+
+	public exportAnimation(
+		outputFilename: string,
+		options: AnimationOptions,
+	): Promise<void> {
+		return invoke("export_simulation_animation", {
+			filename: this._filename,
+			outputFilename: outputFilename,
+			options: options,
+		}).then(() => {});
+	}
+
 	public loadData(): Promise<void> {
 		return new Promise((resolve, reject) => {
 			const url =
@@ -285,8 +718,8 @@ export class QCASimulation {
 						response
 							.arrayBuffer()
 							.then((buffer) => {
-								const data = new Float64Array(buffer);
-								const num_samples = this._metadata.num_samples;
+								const frame = parseSimulationFrame(buffer);
+								const num_samples = frame.numSamples;
 
 								this._clockData = [
 									new Float64Array(num_samples),
@@ -295,54 +728,23 @@ export class QCASimulation {
 									new Float64Array(num_samples),
 								];
 
-								for (
-									let i = 0;
-									i < this._clockData.length;
-									i++
-								) {
-									this._clockData[i] = new Float64Array(
-										num_samples,
-									);
-									for (let j = 0; j < num_samples; j++) {
-										this._clockData[i][j] =
-											data[i * num_samples + j];
-									}
-								}
-
-								let buf_offset =
-									this._clockData.length * num_samples;
-								for (const cell of this._metadata
-									.stored_cells) {
-									const layer = cell.layer;
-									const arch_id =
-										this._design.layers[layer]
-											.cell_architecture_id;
-									const arch =
-										this._design.cell_architectures.get(
-											arch_id,
-										)!;
-									const polarization_n = arch.dot_count / 4;
-									const cellData: Float64Array[] = [];
-									for (let i = 0; i < polarization_n; i++) {
-										cellData.push(
-											new Float64Array(num_samples),
-										);
-									}
-
-									for (let i = 0; i < num_samples; i++) {
-										for (
-											let j = 0;
-											j < polarization_n;
-											j++
-										) {
-											cellData[j][i] = data[buf_offset];
-											buf_offset++;
-										}
+								for (const signal of frame.signals) {
+									switch (signal.kind) {
+										case SignalType.CLOCK:
+											this._clockData[signal.clock!] =
+												signal.data;
+											break;
+										case SignalType.CELL:
+											const key =
+												signal.cell!.toString();
+											if (!this._cellData.has(key)) {
+												this._cellData.set(key, []);
+											}
+											this._cellData.get(key)![
+												signal.polarizationIndex
+											] = signal.data;
+											break;
 									}
-									this._cellData.set(
-										cell.toString(),
-										cellData,
-									);
 								}
 
 								resolve();
@@ -366,6 +768,27 @@ export class QCASimulation {
 	}
 }
 
+export interface ArchiveEntry {
+	path: string;
+	offset: number;
+	size: number;
+}
+
+export interface SimulationFileInfo {
+	design: QCADesign;
+	metadata: QCASimulationMetadata;
+	file_size: number;
+	entries: ArchiveEntry[];
+}
+
+export function inspectSimulationFile(
+	filename: string,
+): Promise<SimulationFileInfo> {
+	return invoke("inspect_simulation_file", {
+		filename: filename,
+	}) as Promise<SimulationFileInfo>;
+}
+
 export function loadSimulationFromFile(
 	filename: string,
 ): Promise<QCASimulation> {
@@ -387,3 +810,62 @@ export function loadSimulationFromFile(
 			});
 	});
 }
+
+export interface DiffOptions {
+	align_by?: "cell_index" | "label";
+	tolerance?: number;
+	max_exceedances?: number;
+	clock_threshold?: number;
+	logical_threshold?: number;
+	value_threshold?: number;
+}
+
+export interface SignalDiff {
+	name: string;
+	a: SignalIndex;
+	b: SignalIndex;
+	max_deviation: number;
+	rms_deviation: number;
+	exceedances: number[];
+	digital_mismatch: boolean;
+}
+
+export interface SimulationDiff {
+	num_samples: [number, number];
+	signals: SignalDiff[];
+	unmatched_a: string[];
+	unmatched_b: string[];
+	truth_tables_differ: boolean;
+	passed: boolean;
+}
+
+export function diffSimulations(
+	a: string,
+	b: string,
+	options: DiffOptions | undefined = undefined,
+): Promise<SimulationDiff> {
+	return invoke("diff_simulations", {
+		a: a,
+		b: b,
+		options: options ?? null,
+	}) as Promise<SimulationDiff>;
+}
+
+export type MergeMode = "concatenate" | "union";
+
+export interface MergeReport {
+	design_hash: string;
+	metadata: QCASimulationMetadata;
+}
+
+export function mergeSimulations(
+	filenames: string[],
+	outputFilename: string,
+	mode: MergeMode,
+): Promise<MergeReport> {
+	return invoke("merge_simulations", {
+		filenames: filenames,
+		outputFilename: outputFilename,
+		mode: mode,
+	}) as Promise<MergeReport>;
+}
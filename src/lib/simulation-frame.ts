@@ -0,0 +1,97 @@
+import { CellIndex } from "./Cell";
+
+const FRAME_MAGIC = "QCSF";
+const FRAME_VERSION = 1;
+const FRAME_HEADER_SIZE = 48;
+const CLOCK_LAYER = 0xffffffff;
+
+export enum FrameSampleType {
+	F64 = 0,
+	F32 = 1,
+}
+
+export interface FrameSignal {
+	kind: number;
+	name: string;
+	cell: CellIndex | undefined;
+	clock: number | undefined;
+	polarizationIndex: number;
+	data: Float64Array;
+}
+
+export interface SimulationFrame {
+	numSamples: number;
+	firstSample: number;
+	stride: number;
+	totalSamples: number;
+	signals: FrameSignal[];
+}
+
+function readSamples(
+	buffer: ArrayBuffer,
+	dtype: number,
+	offset: number,
+	length: number,
+): Float64Array {
+	switch (dtype) {
+		case FrameSampleType.F64:
+			return new Float64Array(buffer, offset, length);
+		case FrameSampleType.F32:
+			return Float64Array.from(new Float32Array(buffer, offset, length));
+		default:
+			throw new Error(`Unsupported frame sample type: ${dtype}`);
+	}
+}
+
+export function parseSimulationFrame(buffer: ArrayBuffer): SimulationFrame {
+	if (buffer.byteLength < FRAME_HEADER_SIZE) {
+		throw new Error("Simulation frame is truncated");
+	}
+	const view = new DataView(buffer);
+	const magic = new TextDecoder().decode(new Uint8Array(buffer, 0, 4));
+	if (magic !== FRAME_MAGIC) {
+		throw new Error("Invalid simulation frame magic");
+	}
+	const version = view.getUint16(4, true);
+	if (version !== FRAME_VERSION) {
+		throw new Error(`Unsupported simulation frame version: ${version}`);
+	}
+	if (view.getUint8(6) !== 0) {
+		throw new Error("Only little-endian simulation frames are supported");
+	}
+
+	const numSamples = Number(view.getBigUint64(8, true));
+	const numSignals = view.getUint32(16, true);
+	const firstSample = Number(view.getBigUint64(24, true));
+	const stride = Number(view.getBigUint64(32, true));
+	const totalSamples = Number(view.getBigUint64(40, true));
+
+	const decoder = new TextDecoder();
+	const signals: FrameSignal[] = [];
+	let pos = FRAME_HEADER_SIZE;
+	for (let i = 0; i < numSignals; i++) {
+		const kind = view.getUint8(pos);
+		const dtype = view.getUint8(pos + 1);
+		const polarizationIndex = view.getUint16(pos + 2, true);
+		const layer = view.getUint32(pos + 4, true);
+		const cell = view.getUint32(pos + 8, true);
+		const offset = Number(view.getBigUint64(pos + 12, true));
+		const length = Number(view.getBigUint64(pos + 20, true));
+		const nameLength = view.getUint16(pos + 28, true);
+		const name = decoder.decode(
+			new Uint8Array(buffer, pos + 30, nameLength),
+		);
+		pos += 30 + nameLength;
+
+		signals.push({
+			kind,
+			name,
+			cell: layer === CLOCK_LAYER ? undefined : new CellIndex(layer, cell),
+			clock: layer === CLOCK_LAYER ? cell : undefined,
+			polarizationIndex,
+			data: readSamples(buffer, dtype, offset, length),
+		});
+	}
+
+	return { numSamples, firstSample, stride, totalSamples, signals };
+}
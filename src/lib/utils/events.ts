@@ -1,6 +1,7 @@
 export const EVENT_NEW_FILE = "newFile";
 export const EVENT_OPEN_DESIGN = "openDesign";
 export const EVENT_OPEN_SIMULATION = "openSimulation";
+export const EVENT_MERGE_SIMULATIONS = "mergeSimulations";
 export const EVENT_SAVE_FILE = "saveFile";
 export const EVENT_SAVE_FILE_AS = "saveFileAs";
 